@@ -1,13 +1,18 @@
 use proc_macro::TokenStream as TS;
 use proc_macro2::TokenStream;
 use quote::{ToTokens, quote};
-use syn::{Data, DeriveInput, Field, Fields, FieldsNamed, Meta, parse_macro_input};
+use syn::{Data, DeriveInput, Field, Fields, Ident, Meta, parse_macro_input};
+
+mod table;
 
 /// A proc macro for implementign the [comms::Parquet] trait.
 /// Make shure all type impl [comms::comms_types::AppendFromColumn]
 /// and [comms::comms_types::ToColumn] and all types results in a
 /// column of the same length.
-#[proc_macro_derive(Parquet, attributes(parquet_type))]
+///
+/// Fields marked `#[parquet_skip]`, e.g. caches, are not written,
+/// and are initialized with [Default::default] when read.
+#[proc_macro_derive(Parquet, attributes(parquet_type, parquet_skip))]
 pub fn parquet(input: TS) -> TS {
     let DeriveInput { ident, data, .. } = parse_macro_input!(input as DeriveInput);
 
@@ -18,8 +23,14 @@ pub fn parquet(input: TS) -> TS {
         panic!("Can only be for named structs");
     };
 
+    let (skipped, fields): (Vec<_>, Vec<_>) = fields.named.iter().partition(|f| is_skipped(f));
+    let skipped: Vec<_> = skipped
+        .into_iter()
+        .map(|f| f.ident.as_ref().expect("Field must have a name"))
+        .collect();
+
     let to = to_parquet(&fields);
-    let from = from_parquet(&fields);
+    let from = from_parquet(&fields, &skipped);
 
     let code = quote! {
         impl ::comms::Parquet for #ident {
@@ -31,6 +42,52 @@ pub fn parquet(input: TS) -> TS {
     code.into()
 }
 
+/// A proc macro for columnar tables of `rusty_roads`, i.e. structs of [Vec]s holding a column each.
+///
+/// Generates the row type, a view type borrowing a row, the key type, a field holding the hash indexes of the
+/// key and unique columns, and implementations of `Insertable`, `Deleteable`, `Updatable` and `Queryable`, along with
+/// `get`, `row`, `rows`, `view`, `iter`, `len`, `is_empty`, a `set_<column>` per column but an auto incremented key,
+/// a `find_by_<column>` per unique column, and `push`.
+///
+/// Key and unique columns must be private, so they are only changed through the table and its indexes stay up to date.
+/// A getter named after each of them borrows the column as a slice.
+///
+/// - `#[table(row = Row, key = Key, view = View)]` names the row, key and view types,
+///   by default `<Table>Row`, `<Table>Key` and `<Row>View`
+/// - `#[table(key)]` makes a column part of the key, which inserting an existing key deduplicates.
+///   `#[table(key(Wrapper))]` wraps the column in the key type in the newtype `Wrapper`, e.g. the key of another table
+/// - `#[table(key, auto_increment)]` assigns the last key plus one to inserted rows instead of deduplicating keys,
+///   and keeps the keys from being changed afterwards
+/// - `#[table(unique)]` makes inserting a row with an existing value of the column return the key of that row instead
+/// - `#[table(indexes)]` marks the field holding the indexes, of type `<Table>Indexes`,
+///   which should also be marked `#[parquet_skip]`
+#[proc_macro_derive(Table, attributes(table))]
+pub fn table(input: TS) -> TS {
+    let DeriveInput {
+        ident,
+        vis,
+        attrs,
+        data,
+        ..
+    } = parse_macro_input!(input as DeriveInput);
+
+    let Data::Struct(data) = data else {
+        panic!("Can only be implemented for structs");
+    };
+    let Fields::Named(fields) = data.fields else {
+        panic!("Can only be for named structs");
+    };
+
+    table::table(&ident, &vis, &attrs, &fields).into()
+}
+
+fn is_skipped(field: &Field) -> bool {
+    field
+        .attrs
+        .iter()
+        .any(|x| x.path().is_ident("parquet_skip"))
+}
+
 fn parse_field(field: &Field) -> (TokenStream, Option<TokenStream>) {
     let ty = field
         .attrs
@@ -54,9 +111,9 @@ fn parse_field(field: &Field) -> (TokenStream, Option<TokenStream>) {
     (name, ty)
 }
 
-fn to_parquet(fields: &FieldsNamed) -> TokenStream {
-    fn create_batch(fields: &FieldsNamed) -> TokenStream {
-        let batch = fields.named.iter().map(parse_field).map(|(name, ty)| {
+fn to_parquet(fields: &[&Field]) -> TokenStream {
+    fn create_batch(fields: &[&Field]) -> TokenStream {
+        let batch = fields.iter().copied().map(parse_field).map(|(name, ty)| {
             let name_str = name.to_string();
             ty.map_or(quote! {self.#name.to_column(#name_str)?}, |ty|
                 quote! {self.#name.into_iter().map(Into::<#ty>::into).collect::<Vec<_>>().to_column(#name_str)?}
@@ -83,8 +140,8 @@ fn to_parquet(fields: &FieldsNamed) -> TokenStream {
     }
 }
 
-fn from_parquet(data: &FieldsNamed) -> TokenStream {
-    let fields = data.named.iter().map(parse_field);
+fn from_parquet(data: &[&Field], skipped: &[&Ident]) -> TokenStream {
+    let fields = data.iter().copied().map(parse_field);
     let init = fields.clone().map(|(name, ty)| {
         ty.map_or(
             quote! {let mut #name = vec![]; },
@@ -124,7 +181,8 @@ fn from_parquet(data: &FieldsNamed) -> TokenStream {
             #(#clean_up)*
 
             Ok(Self {
-                #(#names),*
+                #(#names,)*
+                #(#skipped: ::core::default::Default::default(),)*
             })
         }
     }
@@ -0,0 +1,582 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Attribute, Field, FieldsNamed, GenericArgument, Ident, PathArguments, Type, Visibility};
+
+/// A column of a table, i.e. a field of type `Vec<T>`
+struct Column<'a> {
+    name: &'a Ident,
+    /// The `T` of `Vec<T>`
+    ty: &'a Type,
+    docs: Vec<&'a Attribute>,
+    key: Option<KeyPart>,
+    unique: bool,
+}
+
+/// How a column takes part in the key
+struct KeyPart {
+    /// Newtype wrapping the column in the key type, e.g. the key of another table
+    wrapper: Option<Ident>,
+    /// Assign the last value plus one on insert, instead of the value of the inserted row
+    auto_increment: bool,
+}
+
+/// Names given by `#[table(row = Row, key = Key, view = View)]`
+struct TableNames {
+    row: Ident,
+    key: Ident,
+    view: Ident,
+}
+
+pub fn table(
+    ident: &Ident,
+    vis: &Visibility,
+    attrs: &[Attribute],
+    fields: &FieldsNamed,
+) -> TokenStream {
+    let TableNames { row, key, view } = table_names(ident, attrs);
+    let indexes_ty = format_ident!("{ident}Indexes");
+
+    let mut indexes_field = None;
+    let mut columns = vec![];
+    for field in &fields.named {
+        let name = field.ident.as_ref().expect("Field must have a name");
+        match parse_column(field) {
+            None => indexes_field = Some(name),
+            Some(column) => columns.push(column),
+        }
+    }
+    let indexes = indexes_field.expect("A field must be marked #[table(indexes)]");
+    let keys: Vec<_> = columns.iter().filter(|c| c.key.is_some()).collect();
+    let uniques: Vec<_> = columns.iter().filter(|c| c.unique).collect();
+    assert!(!keys.is_empty(), "A column must be marked #[table(key)]");
+    let auto_increment: Vec<_> = keys
+        .iter()
+        .filter(|c| c.key.as_ref().is_some_and(|k| k.auto_increment))
+        .collect();
+    assert!(
+        auto_increment.is_empty() || keys.len() == 1,
+        "Only a key of a single column can be auto incremented"
+    );
+
+    let names: Vec<_> = columns.iter().map(|c| c.name).collect();
+    let first = names[0];
+
+    // the row type
+    let row_fields = columns.iter().map(|c| {
+        let Column { name, ty, docs, .. } = c;
+        quote! { #(#docs)* pub #name: #ty }
+    });
+    let row_doc = format!("A row of [`{ident}`]");
+    let view_fields = columns.iter().map(|c| {
+        let Column { name, ty, docs, .. } = c;
+        quote! { #(#docs)* pub #name: &'a #ty }
+    });
+    let view_doc = format!("A row of [`{ident}`] borrowed from its columns");
+
+    // the key type, and the hashable value it is indexed by, nesting pairs for keys of more than two columns
+    let key_fields = keys.iter().map(|c| {
+        let ty = c.ty;
+        match &c.key.as_ref().and_then(|k| k.wrapper.as_ref()) {
+            Some(wrapper) => quote! { pub #wrapper },
+            None => quote! { pub #ty },
+        }
+    });
+    let key_doc = format!("Key of a row in [`{ident}`]");
+    let index_ty = nest(keys.iter().map(|c| {
+        let ty = c.ty;
+        quote! { #ty }
+    }));
+    let key_values = |source: TokenStream| {
+        nest(keys.iter().map(|c| {
+            let name = c.name;
+            quote! { ::core::clone::Clone::clone(&#source.#name) }
+        }))
+    };
+    let key_columns = keys
+        .iter()
+        .map(|c| {
+            let name = c.name;
+            quote! { self.#name.iter().cloned() }
+        })
+        .reduce(|a, b| quote! { #a.zip(#b) })
+        .expect("keys are not empty");
+    let key_columns = match keys.len() {
+        1 => {
+            let name = keys[0].name;
+            quote! { self.#name.iter() }
+        }
+        _ => key_columns,
+    };
+    let key_of_value = |value: TokenStream| {
+        let parts = keys.iter().map(|c| {
+            let name = c.name;
+            match &c.key.as_ref().and_then(|k| k.wrapper.as_ref()) {
+                Some(wrapper) => quote! { #wrapper(::core::clone::Clone::clone(&#value.#name)) },
+                None => quote! { ::core::clone::Clone::clone(&#value.#name) },
+            }
+        });
+        quote! { #key(#(#parts),*) }
+    };
+    let key_from_row = key_of_value(quote! { data });
+    let key_lookup = nest(keys.iter().enumerate().map(|(i, c)| {
+        let i = syn::Index::from(i);
+        match &c.key.as_ref().and_then(|k| k.wrapper.as_ref()) {
+            Some(_) => quote! { ::core::clone::Clone::clone(&key.#i.0) },
+            None => quote! { ::core::clone::Clone::clone(&key.#i) },
+        }
+    }));
+    // the hashable value of the key at `index`, with `value` in place of the column `replaced` if given
+    let index_value_at = |replaced: Option<&Ident>| {
+        nest(keys.iter().map(|c| {
+            let name = c.name;
+            match replaced == Some(name) {
+                true => quote! { ::core::clone::Clone::clone(&value) },
+                false => quote! { ::core::clone::Clone::clone(&self.#name[index]) },
+            }
+        }))
+    };
+    let key_at = {
+        let parts = keys.iter().map(|c| {
+            let name = c.name;
+            match &c.key.as_ref().and_then(|k| k.wrapper.as_ref()) {
+                Some(wrapper) => {
+                    quote! { #wrapper(::core::clone::Clone::clone(&self.#name[index])) }
+                }
+                None => quote! { ::core::clone::Clone::clone(&self.#name[index]) },
+            }
+        });
+        quote! { #key(#(#parts),*) }
+    };
+
+    // deduplication on insert
+    let dedup_key = match auto_increment.is_empty() {
+        true => quote! {
+            if let ::core::option::Option::Some(index) =
+                ::rusty_roads::Queryable::find_index(self, &#key_from_row)
+            {
+                return self.key_at(index);
+            }
+        },
+        false => quote! {},
+    };
+    let dedup_uniques = uniques.iter().map(|c| {
+        let find = format_ident!("find_by_{}", c.name);
+        let name = c.name;
+        quote! {
+            if let ::core::option::Option::Some(index) = self.#find(&data.#name) {
+                return self.key_at(index);
+            }
+        }
+    });
+    let assign_key = auto_increment.first().map(|c| {
+        let name = c.name;
+        quote! {
+            let data = #row {
+                #name: self.#name.last().map_or(0, |last| last + 1),
+                ..data
+            };
+        }
+    });
+
+    let unique_indexes = uniques.iter().map(|c| {
+        let Column { name, ty, .. } = c;
+        quote! { #name: ::rusty_roads::KeyIndex<#ty> }
+    });
+    let unique_pushes = uniques.iter().map(|c| {
+        let name = c.name;
+        quote! {
+            self.#indexes.#name.push(::core::clone::Clone::clone(&data.#name), self.#name.iter());
+        }
+    });
+    let unique_removes = uniques.iter().map(|c| {
+        let name = c.name;
+        quote! {
+            self.#indexes.#name.remove(index, &row.#name, self.#name.iter());
+        }
+    });
+    // read-only access to the indexed columns, which are private so they cannot be edited behind the back of the indexes
+    let getters = columns
+        .iter()
+        .filter(|c| c.key.is_some() || c.unique)
+        .map(|c| {
+            let Column { name, ty, docs, .. } = c;
+            quote! {
+                #(#docs)*
+                pub fn #name(&self) -> &[#ty] {
+                    &self.#name
+                }
+            }
+        });
+    let unique_finds = uniques.iter().map(|c| {
+        let Column { name, ty, .. } = c;
+        let find = format_ident!("find_by_{name}");
+        let doc = format!("Finds the index of the row with the unique `{name}`.");
+        quote! {
+            #[doc = #doc]
+            pub fn #find<Q>(&self, #name: &Q) -> ::core::option::Option<usize>
+            where
+                #ty: ::std::borrow::Borrow<Q>,
+                Q: ::std::hash::Hash + ::core::cmp::Eq + ?::core::marker::Sized,
+            {
+                self.#indexes.#name.find(#name, self.#name.iter())
+            }
+        }
+    });
+    let key_of_removed = key_values(quote! { row });
+    let key_of_pushed = key_values(quote! { data });
+
+    // updates, refusing values that would duplicate the key or a unique column of another row
+    let old_index_value = index_value_at(None);
+    let key_of_updated = key_values(quote! { row });
+    let check_uniques = uniques.iter().map(|c| {
+        let name = c.name;
+        let find = format_ident!("find_by_{name}");
+        let column = name.to_string();
+        quote! {
+            if self.#find(&row.#name).is_some_and(|i| i != index) {
+                return ::core::result::Result::Err(::rusty_roads::UpdateError::Duplicate(#column));
+            }
+        }
+    });
+    let unique_replaces = uniques.iter().map(|c| {
+        let name = c.name;
+        quote! {
+            self.#indexes.#name.replace(index, &old.#name, ::core::clone::Clone::clone(&self.#name[index]), self.#name.iter());
+        }
+    });
+    // an auto incremented key is only ever assigned on insert, so a changed key could be assigned again
+    let check_auto_increment = auto_increment.first().map(|_| {
+        quote! {
+            if new_key != old_key {
+                return ::core::result::Result::Err(::rusty_roads::UpdateError::KeyChanged);
+            }
+        }
+    });
+    let settable = columns
+        .iter()
+        .filter(|c| !c.key.as_ref().is_some_and(|k| k.auto_increment));
+    let setters = settable.map(|c| {
+        let Column { name, ty, .. } = c;
+        let set = format_ident!("set_{name}");
+        let column = name.to_string();
+        let check_unique = c.unique.then(|| {
+            let find = format_ident!("find_by_{name}");
+            quote! {
+                if self.#find(&value).is_some_and(|i| i != index) {
+                    return ::core::result::Result::Err(::rusty_roads::UpdateError::Duplicate(#column));
+                }
+            }
+        });
+        let check_key = c.key.as_ref().map(|_| {
+            let new_key = index_value_at(Some(name));
+            quote! {
+                let old_key = #old_index_value;
+                let new_key = #new_key;
+                if self.#indexes.primary_key.find(&new_key, #key_columns).is_some_and(|i| i != index) {
+                    return ::core::result::Result::Err(::rusty_roads::UpdateError::Duplicate("key"));
+                }
+            }
+        });
+        let replace_unique = c.unique.then(|| {
+            quote! {
+                self.#indexes.#name.replace(index, &old, ::core::clone::Clone::clone(&self.#name[index]), self.#name.iter());
+            }
+        });
+        let replace_key = c.key.as_ref().map(|_| {
+            quote! {
+                self.#indexes.primary_key.replace(index, &old_key, new_key, #key_columns);
+            }
+        });
+        let doc = format!(
+            "Sets `{name}` of the row with `key`, and returns the old value.\n\n\
+            # Errors\n\n\
+            This function will return an error if no row has `key`{}.",
+            match (c.key.is_some(), c.unique) {
+                (true, _) => ", or if the key would be the key of another row",
+                (false, true) => ", or if another row has the same value",
+                (false, false) => "",
+            }
+        );
+        quote! {
+            #[doc = #doc]
+            pub fn #set(&mut self, key: &#key, value: #ty) -> ::core::result::Result<#ty, ::rusty_roads::UpdateError> {
+                let index = ::rusty_roads::Queryable::find_index(self, key)
+                    .ok_or(::rusty_roads::UpdateError::NotFound)?;
+                #check_unique
+                #check_key
+                let old = ::core::mem::replace(&mut self.#name[index], value);
+                #replace_unique
+                #replace_key
+                ::core::result::Result::Ok(old)
+            }
+        }
+    });
+
+    let indexes_doc = format!("Hash indexes of the key and unique columns of [`{ident}`]");
+    quote! {
+        #[doc = #row_doc]
+        #[derive(Debug, Clone)]
+        #vis struct #row {
+            #(#row_fields),*
+        }
+
+        #[doc = #view_doc]
+        #[derive(Debug, Clone, Copy)]
+        #vis struct #view<'a> {
+            #(#view_fields),*
+        }
+
+        impl #view<'_> {
+            /// Clones the borrowed row.
+            pub fn to_row(&self) -> #row {
+                #row {
+                    #(#names: ::core::clone::Clone::clone(self.#names)),*
+                }
+            }
+        }
+
+        #[doc = #key_doc]
+        #[derive(Debug, Clone, Copy)]
+        #vis struct #key(#(#key_fields),*);
+
+        #[doc = #indexes_doc]
+        #[derive(Debug, Clone, Default)]
+        struct #indexes_ty {
+            primary_key: ::rusty_roads::KeyIndex<#index_ty>,
+            #(#unique_indexes),*
+        }
+
+        impl #ident {
+            /// Number of rows
+            pub fn len(&self) -> usize {
+                self.#first.len()
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.#first.is_empty()
+            }
+
+            /// Reads the row at `index`.
+            pub fn row(&self, index: usize) -> ::core::option::Option<#row> {
+                ::core::option::Option::Some(#row {
+                    #(#names: ::core::clone::Clone::clone(self.#names.get(index)?)),*
+                })
+            }
+
+            /// Reads every row, in order.
+            pub fn rows(&self) -> impl ::core::iter::Iterator<Item = #row> + '_ {
+                (0..self.len()).filter_map(|index| self.row(index))
+            }
+
+            /// Reads the row with `key`.
+            pub fn get(&self, key: &#key) -> ::core::option::Option<#row> {
+                self.row(::rusty_roads::Queryable::find_index(self, key)?)
+            }
+
+            /// Borrows the row at `index`.
+            pub fn view(&self, index: usize) -> ::core::option::Option<#view<'_>> {
+                ::core::option::Option::Some(#view {
+                    #(#names: self.#names.get(index)?),*
+                })
+            }
+
+            /// Borrows every row, in order.
+            pub fn iter(&self) -> impl ::core::iter::Iterator<Item = #view<'_>> + '_ {
+                (0..self.len()).filter_map(|index| self.view(index))
+            }
+
+            #(#getters)*
+
+            #(#unique_finds)*
+
+            #(#setters)*
+
+            /// Appends a row as is, without deduplicating or assigning a new key.
+            pub(crate) fn push(&mut self, data: #row) {
+                self.#indexes.primary_key.push(#key_of_pushed, #key_columns);
+                #(#unique_pushes)*
+                #(self.#names.push(data.#names);)*
+            }
+
+            #[allow(dead_code)]
+            fn key_at(&self, index: usize) -> #key {
+                #key_at
+            }
+        }
+
+        impl ::rusty_roads::Insertable<#row> for #ident {
+            type Key = #key;
+
+            fn insert(&mut self, data: #row) -> Self::Key {
+                // Does not insert duplicates
+                #dedup_key
+                #(#dedup_uniques)*
+                #assign_key
+                let key = #key_from_row;
+                self.push(data);
+                key
+            }
+        }
+
+        impl ::rusty_roads::Deleteable<#key> for #ident {
+            type Output = #row;
+
+            fn delete(&mut self, key: &#key) -> ::core::option::Option<Self::Output> {
+                let index = ::rusty_roads::Queryable::find_index(self, key)?;
+                let row = #row {
+                    #(#names: self.#names.remove(index)),*
+                };
+                self.#indexes.primary_key.remove(index, &#key_of_removed, #key_columns);
+                #(#unique_removes)*
+                ::core::option::Option::Some(row)
+            }
+        }
+
+        impl ::rusty_roads::Updatable<#key> for #ident {
+            type Row = #row;
+
+            fn update(&mut self, key: &#key, row: #row) -> ::core::result::Result<#row, ::rusty_roads::UpdateError> {
+                let index = ::rusty_roads::Queryable::find_index(self, key)
+                    .ok_or(::rusty_roads::UpdateError::NotFound)?;
+                let old_key = #old_index_value;
+                let new_key = #key_of_updated;
+                #check_auto_increment
+                if self.#indexes.primary_key.find(&new_key, #key_columns).is_some_and(|i| i != index) {
+                    return ::core::result::Result::Err(::rusty_roads::UpdateError::Duplicate("key"));
+                }
+                #(#check_uniques)*
+                let old = #row {
+                    #(#names: ::core::mem::replace(&mut self.#names[index], row.#names)),*
+                };
+                self.#indexes.primary_key.replace(index, &old_key, new_key, #key_columns);
+                #(#unique_replaces)*
+                ::core::result::Result::Ok(old)
+            }
+        }
+
+        impl ::rusty_roads::Queryable<#key> for #ident {
+            fn find_index(&self, key: &#key) -> ::core::option::Option<usize> {
+                self.#indexes.primary_key.find(&#key_lookup, #key_columns)
+            }
+        }
+    }
+}
+
+/// Nests `parts` into pairs from the left, e.g. `((a, b), c)`, or returns a single part as is
+fn nest(parts: impl Iterator<Item = TokenStream>) -> TokenStream {
+    parts
+        .reduce(|a, b| quote! { (#a, #b) })
+        .expect("keys are not empty")
+}
+
+fn table_names(ident: &Ident, attrs: &[Attribute]) -> TableNames {
+    let mut row = None;
+    let mut key = None;
+    let mut view = None;
+    for attr in attrs.iter().filter(|a| a.path().is_ident("table")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("row") {
+                row = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("key") {
+                key = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("view") {
+                view = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected `row`, `key` or `view`"));
+            }
+            Ok(())
+        })
+        .expect(
+            "table attribute must be on format #[table(row = `Row`, key = `Key`, view = `View`)]",
+        );
+    }
+    let row = row.unwrap_or_else(|| format_ident!("{ident}Row"));
+    TableNames {
+        view: view.unwrap_or_else(|| format_ident!("{row}View")),
+        key: key.unwrap_or_else(|| format_ident!("{ident}Key")),
+        row,
+    }
+}
+
+/// Parses a column, or returns [`None`] for the field holding the indexes
+fn parse_column(field: &Field) -> Option<Column<'_>> {
+    let name = field.ident.as_ref().expect("Field must have a name");
+    let mut key = None;
+    let mut unique = false;
+    let mut indexes = false;
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("table")) {
+        let mut wrapper = None;
+        let mut is_key = false;
+        let mut auto_increment = false;
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("key") {
+                is_key = true;
+                if meta.input.peek(syn::token::Paren) {
+                    meta.parse_nested_meta(|inner| {
+                        wrapper = inner.path.get_ident().cloned();
+                        Ok(())
+                    })?;
+                }
+            } else if meta.path.is_ident("auto_increment") {
+                auto_increment = true;
+            } else if meta.path.is_ident("unique") {
+                unique = true;
+            } else if meta.path.is_ident("indexes") {
+                indexes = true;
+            } else {
+                return Err(meta.error(
+                    "expected `key`, `key(Wrapper)`, `auto_increment`, `unique` or `indexes`",
+                ));
+            }
+            Ok(())
+        })
+        .unwrap_or_else(|e| panic!("invalid table attribute on `{name}`: {e}"));
+        assert!(
+            is_key || !auto_increment,
+            "`auto_increment` is only allowed on a key"
+        );
+        if is_key {
+            key = Some(KeyPart {
+                wrapper,
+                auto_increment,
+            });
+        }
+    }
+    if indexes {
+        return None;
+    }
+    assert!(
+        (key.is_none() && !unique) || matches!(field.vis, Visibility::Inherited),
+        "Column `{name}` is indexed, so it must be private to only be changed through the table"
+    );
+
+    Some(Column {
+        name,
+        ty: vec_item(&field.ty).unwrap_or_else(|| panic!("Column `{name}` must be of type Vec<T>")),
+        docs: field
+            .attrs
+            .iter()
+            .filter(|a| a.path().is_ident("doc"))
+            .collect(),
+        key,
+        unique,
+    })
+}
+
+/// The `T` of `Vec<T>`
+fn vec_item(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if segment.ident != "Vec" {
+        return None;
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    match args.args.first()? {
+        GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    }
+}
@@ -158,6 +158,21 @@ mod tests {
         assert_eq!(point! { x: 2.0 , y: 2.0 }, points[0]);
     }
 
+    #[test]
+    fn map_point_to_shared_index() {
+        let road_network = Roads::new();
+        let shared = rusty_roads::SharedRoadIndex::new(rusty_roads::RoadIndex::from_ids_and_roads(
+            &road_network.ids,
+            &road_network.roads,
+        ));
+
+        let points = vec![point! { x: 1.5, y: 2.3 }];
+
+        let points = obfuscate_points(points.into_iter(), shared.snapshot()).unwrap();
+
+        assert_eq!(point! { x: 1.9 , y: 1.9 }, points[0]);
+    }
+
     #[test]
     fn map_no_point_to_road() {
         let road_network = Roads::new();
@@ -0,0 +1,106 @@
+//! Compares the hash indexes of the tables with the linear scans they replace, on a million roads.
+//!
+//! Run with `cargo bench -p rusty-roads --bench table_index`.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use geo_types::line_string;
+use rusty_roads::{Deleteable, Direction, Insertable, Queryable, Road, RoadKey, Roads};
+
+const ROWS: u64 = 1_000_000;
+/// Linear scans are so slow that only this many are timed
+const SCANS: u64 = 1_000;
+
+fn road(osm_id: u64) -> Road {
+    Road {
+        id: 0,
+        geom: line_string![(x: 0., y: 0.), (x: 1., y: 1.)],
+        osm_id,
+        code: 5113,
+        direction: Direction::Bidirectional,
+        maxspeed: 50,
+        layer: 0,
+        bridge: false,
+        tunnel: false,
+    }
+}
+
+fn time<T>(f: impl FnOnce() -> T) -> Duration {
+    let start = Instant::now();
+    black_box(f());
+    start.elapsed()
+}
+
+fn report(name: &str, elapsed: Duration, count: u64) {
+    println!(
+        "{name:<32} {:>10.2?} total {:>10.2?} each",
+        elapsed,
+        elapsed / count as u32
+    );
+}
+
+fn main() {
+    // spread the osm ids, so they do not follow the order of the rows
+    let osm_id = |i: u64| i.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+
+    let mut roads = Roads::default();
+    let elapsed = time(|| roads.insert_many((0..ROWS).map(|i| road(osm_id(i)))));
+    report("insert", elapsed, ROWS);
+
+    let elapsed = time(|| roads.insert_many((0..ROWS).map(|i| road(osm_id(i)))));
+    report("insert duplicates", elapsed, ROWS);
+
+    let keys: Vec<_> = (0..ROWS).rev().map(RoadKey).collect();
+    let elapsed = time(|| roads.find_many_indexes(&keys));
+    report("find by id", elapsed, ROWS);
+
+    let elapsed = time(|| {
+        keys.iter()
+            .take(SCANS as usize)
+            .map(|key| roads.id().iter().position(|id| *id == key.0))
+            .collect::<Vec<_>>()
+    });
+    report("find by id, linear scan", elapsed, SCANS);
+
+    let elapsed = time(|| {
+        (0..ROWS)
+            .map(|i| roads.find_by_osm_id(&osm_id(i)))
+            .collect::<Vec<_>>()
+    });
+    report("find by osm id", elapsed, ROWS);
+
+    let elapsed = time(|| {
+        (0..SCANS)
+            .map(|i| {
+                roads
+                    .osm_id()
+                    .iter()
+                    .position(|o| *o == osm_id(ROWS - 1 - i))
+            })
+            .collect::<Vec<_>>()
+    });
+    report("find by osm id, linear scan", elapsed, SCANS);
+
+    let deleted: Vec<_> = (0..SCANS).map(|i| RoadKey(i * 997)).collect();
+    let mut columns = (
+        roads.id().to_vec(),
+        roads.geom.clone(),
+        roads.osm_id().to_vec(),
+    );
+    let elapsed = time(|| {
+        for key in &deleted {
+            if let Some(index) = columns.0.iter().position(|id| *id == key.0) {
+                columns.0.remove(index);
+                columns.1.remove(index);
+                columns.2.remove(index);
+            }
+        }
+    });
+    report("delete 3 of 9 columns, linear scan", elapsed, SCANS);
+
+    let elapsed = time(|| roads.delete_many(&deleted));
+    report("delete", elapsed, SCANS);
+    assert_eq!(roads.len() as u64, ROWS - SCANS);
+    assert!(deleted.iter().all(|key| roads.find_index(key).is_none()));
+}
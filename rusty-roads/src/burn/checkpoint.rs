@@ -0,0 +1,301 @@
+use std::fs;
+use std::path::Path;
+
+use burn::{
+    config::ConfigError,
+    data::dataset::Dataset,
+    optim::Optimizer,
+    prelude::*,
+    record::{FullPrecisionSettings, NamedMpkFileRecorder, Recorder, RecorderError},
+    tensor::backend::AutodiffBackend,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::training::fit;
+use super::{
+    EpochMetrics, Model, ModelConfig, RoadVocabulary, SequenceItem, SequenceLoader, TrainingConfig,
+};
+
+/// File in a checkpoint directory holding the [`ModelConfig`]
+pub const MODEL_CONFIG_FILE: &str = "model.json";
+/// File in a checkpoint directory holding the model weights, without the extension added by the recorder
+pub const MODEL_RECORD_FILE: &str = "model";
+/// File in a checkpoint directory holding the [`RoadVocabulary`]
+pub const VOCABULARY_FILE: &str = "vocabulary.json";
+/// File in a checkpoint directory holding the [`TrainingConfig`]
+pub const TRAINING_CONFIG_FILE: &str = "training.json";
+/// File in a checkpoint directory holding the [`TrainingMetadata`]
+pub const METADATA_FILE: &str = "metadata.json";
+/// File in a checkpoint directory holding the optimizer state, without the extension added by the recorder
+pub const OPTIMIZER_RECORD_FILE: &str = "optimizer";
+
+type CheckpointRecorder = NamedMpkFileRecorder<FullPrecisionSettings>;
+
+#[derive(Debug, Error)]
+pub enum CheckpointError {
+    #[error("could not access the checkpoint: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid config: {0}")]
+    Config(#[from] ConfigError),
+    #[error("invalid model record: {0}")]
+    Record(#[from] RecorderError),
+    #[error("invalid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("the vocabulary has {0} tokens, but the model embeds {1}")]
+    VocabularyMismatch(usize, usize),
+    #[error("the vocabulary has {0} tokens, but the model predicts {1} classes")]
+    ClassMismatch(usize, usize),
+    #[error("the checkpoint was trained with a different {0}")]
+    Mismatch(&'static str),
+}
+
+/// What a model in a checkpoint was trained on, and how well it did.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrainingMetadata {
+    /// Hash of the training data, see [`SequenceDataset::hash`](super::SequenceDataset::hash)
+    pub data_hash: String,
+    pub seed: u64,
+    /// Metrics of every epoch trained so far
+    pub epochs: Vec<EpochMetrics>,
+}
+
+impl TrainingMetadata {
+    /// Loads the metadata from the checkpoint directory `dir`, or returns [`None`] if it has none.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the metadata cannot be read or is invalid.
+    pub fn load(dir: &Path) -> Result<Option<Self>, CheckpointError> {
+        let path = dir.join(METADATA_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&fs::read_to_string(path)?)?))
+    }
+
+    fn save(&self, dir: &Path) -> Result<(), CheckpointError> {
+        fs::write(dir.join(METADATA_FILE), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Saves `model`, the config it was initialized from, and the vocabulary it was trained with to the directory `dir`,
+/// which is created if it does not exist.
+///
+/// The [`Target`](super::Target) the model was trained for is saved as part of `config`.
+///
+/// # Errors
+///
+/// This function will return an error if any file cannot be written.
+pub fn save_model<B: Backend>(
+    dir: &Path,
+    model: Model<B>,
+    config: &ModelConfig,
+    vocabulary: &RoadVocabulary,
+) -> Result<(), CheckpointError> {
+    fs::create_dir_all(dir)?;
+    config.save(dir.join(MODEL_CONFIG_FILE))?;
+    fs::write(
+        dir.join(VOCABULARY_FILE),
+        serde_json::to_string(vocabulary)?,
+    )?;
+    model.save_file(dir.join(MODEL_RECORD_FILE), &CheckpointRecorder::new())?;
+    Ok(())
+}
+
+/// Loads a model saved by [`save_model`] from the directory `dir` onto `device`.
+///
+/// # Errors
+///
+/// This function will return an error if any file is missing or invalid,
+/// or if the vocabulary does not fit the model, see [`check_vocabulary`].
+pub fn load_model<B: Backend>(
+    dir: &Path,
+    device: &B::Device,
+) -> Result<(Model<B>, ModelConfig, RoadVocabulary), CheckpointError> {
+    let config = ModelConfig::load(dir.join(MODEL_CONFIG_FILE))?;
+    let vocabulary: RoadVocabulary =
+        serde_json::from_str(&fs::read_to_string(dir.join(VOCABULARY_FILE))?)?;
+    check_vocabulary(&config, &vocabulary)?;
+    let model = config.init::<B>(device).load_file(
+        dir.join(MODEL_RECORD_FILE),
+        &CheckpointRecorder::new(),
+        device,
+    )?;
+    Ok((model, config, vocabulary))
+}
+
+/// Checks that a model initialized from `config` embeds every token of `vocabulary`, and predicts one of them.
+///
+/// # Errors
+///
+/// This function will return an error if the number of tokens differs from the number of roads or classes of the model.
+pub fn check_vocabulary(
+    config: &ModelConfig,
+    vocabulary: &RoadVocabulary,
+) -> Result<(), CheckpointError> {
+    if vocabulary.num_tokens() != config.num_roads {
+        return Err(CheckpointError::VocabularyMismatch(
+            vocabulary.num_tokens(),
+            config.num_roads,
+        ));
+    }
+    if vocabulary.num_tokens() != config.num_classes {
+        return Err(CheckpointError::ClassMismatch(
+            vocabulary.num_tokens(),
+            config.num_classes,
+        ));
+    }
+    Ok(())
+}
+
+/// Like [`train`](super::train), but saves a checkpoint to the directory `dir` after every epoch,
+/// and resumes from the checkpoint in `dir` if there is one.
+///
+/// Resumed training continues exactly as if it had not been interrupted,
+/// as long as `config`, `train` and `valid` are the same as when it started, except for `config.num_epochs`.
+/// `data_hash` identifies the training data, see [`SequenceDataset::hash`](super::SequenceDataset::hash).
+/// Training does nothing if the checkpoint has already been trained for `config.num_epochs`.
+///
+/// # Errors
+///
+/// This function will return an error if the checkpoint cannot be loaded or saved,
+/// or if it was trained on other data or with another config.
+pub fn train_checkpointed<B, D>(
+    dir: &Path,
+    config: &TrainingConfig,
+    vocabulary: &RoadVocabulary,
+    data_hash: &str,
+    device: &B::Device,
+    train: D,
+    valid: SequenceLoader<B::InnerBackend>,
+) -> Result<(Model<B>, TrainingMetadata), CheckpointError>
+where
+    B: AutodiffBackend,
+    D: Dataset<SequenceItem> + 'static,
+{
+    let recorder = CheckpointRecorder::new();
+    let (model, optimizer, epochs) = match TrainingMetadata::load(dir)? {
+        Some(metadata) => {
+            if metadata.data_hash != data_hash {
+                return Err(CheckpointError::Mismatch("data"));
+            }
+            if metadata.seed != config.seed {
+                return Err(CheckpointError::Mismatch("seed"));
+            }
+            let saved = TrainingConfig::load(dir.join(TRAINING_CONFIG_FILE))?
+                .with_num_epochs(config.num_epochs);
+            if serde_json::to_value(saved)? != serde_json::to_value(config)? {
+                return Err(CheckpointError::Mismatch("training config"));
+            }
+            let (model, _, _) = load_model::<B>(dir, device)?;
+            let record = recorder.load(dir.join(OPTIMIZER_RECORD_FILE), device)?;
+            let optimizer = config.optimizer.init().load_record(record);
+            (model, optimizer, metadata.epochs)
+        }
+        None => {
+            B::seed(config.seed);
+            let model = config.model.init::<B>(device);
+            (model, config.optimizer.init(), vec![])
+        }
+    };
+
+    fs::create_dir_all(dir)?;
+    config.save(dir.join(TRAINING_CONFIG_FILE))?;
+    let (model, _, epochs) = fit(
+        config,
+        device,
+        train,
+        valid,
+        model,
+        optimizer,
+        epochs,
+        |model, optimizer, epochs| {
+            save_model(dir, model.clone(), &config.model, vocabulary)?;
+            recorder.record(optimizer.to_record(), dir.join(OPTIMIZER_RECORD_FILE))?;
+            // written last, so an interrupted save resumes from the previous epoch
+            metadata(data_hash, config, epochs).save(dir)
+        },
+    )?;
+    Ok((model, metadata(data_hash, config, &epochs)))
+}
+
+fn metadata(data_hash: &str, config: &TrainingConfig, epochs: &[EpochMetrics]) -> TrainingMetadata {
+    TrainingMetadata {
+        data_hash: data_hash.to_owned(),
+        seed: config.seed,
+        epochs: epochs.to_vec(),
+    }
+}
+
+#[cfg(all(test, feature = "ndarray"))]
+mod tests {
+    use burn::optim::AdamConfig;
+
+    use super::super::{valid_loader, CpuBackend, DatasetConfig, SequenceDataset};
+    use super::*;
+    use crate::map_match::fixtures;
+
+    #[test]
+    fn resumes_deterministically() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let vocabulary = RoadVocabulary::new(roads.iter().map(|(r, _, _)| &r.id));
+        let routes = vec![vec![0, 1, 2], vec![2, 1, 0], vec![1, 2], vec![1, 0]];
+        let dataset =
+            SequenceDataset::from_routes(&routes, &vocabulary, &network, &DatasetConfig::new());
+        let config = |num_epochs| {
+            TrainingConfig::new(
+                ModelConfig::new(vocabulary.num_tokens(), vocabulary.num_tokens())
+                    .with_embedding_size(4)
+                    .with_hidden_size(8),
+                AdamConfig::new(),
+            )
+            .with_num_epochs(num_epochs)
+            .with_batch_size(2)
+        };
+        let device = Default::default();
+        let run = |dir: &Path, config: &TrainingConfig| {
+            train_checkpointed::<CpuBackend, _>(
+                dir,
+                config,
+                &vocabulary,
+                &dataset.hash(),
+                &device,
+                dataset.clone(),
+                valid_loader(dataset.clone(), config.batch_size, &device),
+            )
+        };
+
+        let uninterrupted = tempfile::tempdir().expect("should create temp dir");
+        let (_, expected) = run(uninterrupted.path(), &config(4)).expect("should train");
+        assert_eq!(expected.epochs.len(), 4);
+
+        let interrupted = tempfile::tempdir().expect("should create temp dir");
+        run(interrupted.path(), &config(2)).expect("should train");
+        let (_, resumed) = run(interrupted.path(), &config(4)).expect("should resume");
+        assert_eq!(resumed, expected);
+        assert_eq!(
+            TrainingMetadata::load(interrupted.path()).expect("should load"),
+            Some(expected)
+        );
+
+        let other_data = train_checkpointed::<CpuBackend, _>(
+            interrupted.path(),
+            &config(6),
+            &vocabulary,
+            "other",
+            &device,
+            dataset.clone(),
+            valid_loader(dataset.clone(), 2, &device),
+        );
+        assert!(matches!(other_data, Err(CheckpointError::Mismatch("data"))));
+
+        let other_config = run(interrupted.path(), &config(6).with_learning_rate(0.1));
+        assert!(matches!(
+            other_config,
+            Err(CheckpointError::Mismatch("training config"))
+        ));
+    }
+}
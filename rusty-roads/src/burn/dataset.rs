@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+
+use burn::{
+    data::{dataloader::DataLoaderBuilder, dataset::Dataset},
+    prelude::*,
+};
+use geo::{Bearing, Haversine, Length};
+use petgraph::matrix_graph::IndexType;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{hmm_match_batch, Id, MatchConfig, RoadIndex, RoadNetwork, RoadWithNode, Trajectories};
+
+use super::{SequenceBatcher, SequenceItem, SequenceLoader, PADDING_TOKEN};
+
+/// Number of features of each road in a sequence, see [`encode`]
+pub const NUM_FEATURES: usize = 5;
+
+/// Maps road ids to the tokens the model is trained on, starting after [`PADDING_TOKEN`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RoadVocabulary {
+    ids: Vec<Id>,
+    tokens: HashMap<Id, u32>,
+}
+
+impl RoadVocabulary {
+    /// Assigns a token to every distinct id in `ids`, in order.
+    pub fn new<'i, I: IntoIterator<Item = &'i Id>>(ids: I) -> Self {
+        let mut vocabulary = Self::default();
+        for id in ids {
+            if !vocabulary.tokens.contains_key(id) {
+                vocabulary.ids.push(*id);
+                let token = PADDING_TOKEN + vocabulary.ids.len() as u32;
+                vocabulary.tokens.insert(*id, token);
+            }
+        }
+        vocabulary
+    }
+
+    /// Number of tokens including the padding token, i.e. [`ModelConfig::num_roads`](super::ModelConfig::num_roads)
+    pub fn num_tokens(&self) -> usize {
+        self.ids.len() + 1
+    }
+
+    pub fn token(&self, id: Id) -> Option<u32> {
+        self.tokens.get(&id).copied()
+    }
+
+    pub fn road(&self, token: u32) -> Option<Id> {
+        let index = token.checked_sub(PADDING_TOKEN + 1)?;
+        self.ids.get(index as usize).copied()
+    }
+}
+
+/// Tokens and features of the roads with the given ids, e.g. a matched trajectory, as input to [`Model::forward`](super::Model::forward).
+///
+/// The features of a road are its length in kilometers, its speed limit in 100 km/h, its feature class code in thousands,
+/// and the sine and cosine of its heading in the direction it is driven, which is inferred from the roads before and after it.
+///
+/// Roads missing from `vocabulary` or `network` are skipped.
+pub fn encode<Idx: IndexType>(
+    ids: &[Id],
+    vocabulary: &RoadVocabulary,
+    network: &RoadNetwork<Idx>,
+) -> (Vec<u32>, Vec<[f32; NUM_FEATURES]>) {
+    let roads: Vec<_> = ids
+        .iter()
+        .filter_map(|id| Some((vocabulary.token(*id)?, network.road(*id)?)))
+        .collect();
+
+    let mut tokens = Vec::with_capacity(roads.len());
+    let mut features = Vec::with_capacity(roads.len());
+    let mut prev_target = None;
+    for (i, (token, road)) in roads.iter().enumerate() {
+        let reversed = match (prev_target, roads.get(i + 1)) {
+            // continue from where the previous road ended
+            (Some(node), _) => road.source != node && road.target == node,
+            // head towards the next road
+            (None, Some((_, next))) => !touches(road.target, next) && touches(road.source, next),
+            (None, None) => false,
+        };
+        prev_target = Some(match reversed {
+            true => road.source,
+            false => road.target,
+        });
+        tokens.push(*token);
+        features.push(road_features(road, reversed));
+    }
+    (tokens, features)
+}
+
+fn touches(node: i32, road: &RoadWithNode) -> bool {
+    road.source == node || road.target == node
+}
+
+fn road_features(road: &RoadWithNode, reversed: bool) -> [f32; NUM_FEATURES] {
+    let road = road.road;
+    let heading = match (road.geom.points().next(), road.geom.points().next_back()) {
+        (Some(first), Some(last)) if first != last => match reversed {
+            true => Haversine.bearing(last, first),
+            false => Haversine.bearing(first, last),
+        }
+        .to_radians(),
+        _ => 0.,
+    };
+    [
+        (Haversine.length(&road.geom) / 1000.) as f32,
+        f32::from(road.maxspeed) / 100.,
+        f32::from(road.code) / 1000.,
+        heading.sin() as f32,
+        heading.cos() as f32,
+    ]
+}
+
+/// What the model learns to predict from a partial trajectory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Target {
+    /// The road driven right after it
+    NextRoad,
+    /// The last road of the trajectory
+    Destination,
+}
+
+#[derive(Config, Debug)]
+pub struct DatasetConfig {
+    /// Should be the [`target`](super::ModelConfig::target) of the model trained on the dataset
+    #[config(default = "Target::NextRoad")]
+    pub target: Target,
+    /// Minimum number of roads in a sequence
+    #[config(default = 1)]
+    pub min_length: usize,
+    /// Maximum number of roads in a sequence, longer sequences keep their last roads
+    #[config(default = 64)]
+    pub max_length: usize,
+    /// Fraction of trajectories used for validation
+    #[config(default = 0.2)]
+    pub valid_fraction: f64,
+    /// Seed for splitting trajectories into training and validation
+    #[config(default = 42)]
+    pub seed: u64,
+}
+
+/// Every prefix of a set of matched trajectories, encoded as a sequence of road tokens and features, with its [`Target`].
+#[derive(Debug, Clone, Default)]
+pub struct SequenceDataset {
+    items: Vec<SequenceItem>,
+}
+
+impl Dataset<SequenceItem> for SequenceDataset {
+    fn get(&self, index: usize) -> Option<SequenceItem> {
+        self.items.get(index).cloned()
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+}
+
+impl SequenceDataset {
+    /// SHA-256 of the items in order, to identify the data a model was trained on.
+    pub fn hash(&self) -> String {
+        let mut hasher = Sha256::new();
+        for item in &self.items {
+            hasher.update((item.tokens.len() as u64).to_le_bytes());
+            item.tokens
+                .iter()
+                .for_each(|t| hasher.update(t.to_le_bytes()));
+            item.features
+                .iter()
+                .flatten()
+                .for_each(|f| hasher.update(f.to_le_bytes()));
+            hasher.update(item.target.to_le_bytes());
+        }
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Creates a dataset from the roads driven by each trajectory, see [`encode`].
+    pub fn from_routes<Idx: IndexType>(
+        routes: &[Vec<Id>],
+        vocabulary: &RoadVocabulary,
+        network: &RoadNetwork<Idx>,
+        config: &DatasetConfig,
+    ) -> Self {
+        let items = routes
+            .iter()
+            .flat_map(|route| {
+                let (tokens, features) = encode(route, vocabulary, network);
+                items(tokens, features, config)
+            })
+            .collect();
+        Self { items }
+    }
+
+    /// Like [`SequenceDataset::from_routes`], but splits the routes into a training and a validation dataset.
+    ///
+    /// Whole routes are split, so the prefixes of a route are never in both.
+    pub fn split<Idx: IndexType>(
+        routes: &[Vec<Id>],
+        vocabulary: &RoadVocabulary,
+        network: &RoadNetwork<Idx>,
+        config: &DatasetConfig,
+    ) -> (Self, Self) {
+        let mut shuffled: Vec<_> = routes.to_vec();
+        shuffled.shuffle(&mut StdRng::seed_from_u64(config.seed));
+        let valid = (shuffled.len() as f64 * config.valid_fraction).round() as usize;
+        let train = shuffled.split_off(valid.min(shuffled.len()));
+        (
+            Self::from_routes(&train, vocabulary, network, config),
+            Self::from_routes(&shuffled, vocabulary, network, config),
+        )
+    }
+
+    /// Map matches `trajectories` with [`hmm_match_batch`] and splits the matched routes, see [`SequenceDataset::split`].
+    /// Trajectories that cannot be matched are left out.
+    ///
+    /// Trajectories written as Parquet by `comms` can be read with [`Parquet::from_parquet`](comms::Parquet::from_parquet).
+    pub fn from_trajectories<Idx: IndexType + Send + Sync>(
+        trajectories: &Trajectories,
+        index: &RoadIndex,
+        network: &RoadNetwork<Idx>,
+        vocabulary: &RoadVocabulary,
+        match_config: &MatchConfig,
+        config: &DatasetConfig,
+    ) -> (Self, Self) {
+        let routes: Vec<_> = hmm_match_batch(trajectories, index, network, match_config)
+            .results
+            .into_iter()
+            .filter_map(|(_, roads)| roads.ok())
+            .collect();
+        Self::split(&routes, vocabulary, network, config)
+    }
+}
+
+/// The prefixes of a route and their targets.
+fn items(
+    tokens: Vec<u32>,
+    features: Vec<[f32; NUM_FEATURES]>,
+    config: &DatasetConfig,
+) -> impl Iterator<Item = SequenceItem> + '_ {
+    let destination = tokens.last().copied();
+    (config.min_length.max(1)..tokens.len()).map(move |end| {
+        let start = end.saturating_sub(config.max_length);
+        let target = match config.target {
+            Target::NextRoad => tokens[end],
+            Target::Destination => destination.unwrap_or(PADDING_TOKEN),
+        };
+        SequenceItem {
+            tokens: tokens[start..end].to_vec(),
+            features: features[start..end].to_vec(),
+            target,
+        }
+    })
+}
+
+/// Batches `valid` for [`train`](super::train) and [`evaluate`](super::evaluate), in order.
+///
+/// The training data is passed to [`train`](super::train) as is, as it is shuffled anew every epoch.
+pub fn valid_loader<B: Backend>(
+    valid: SequenceDataset,
+    batch_size: usize,
+    device: &B::Device,
+) -> SequenceLoader<B> {
+    DataLoaderBuilder::new(SequenceBatcher::<B>::new(device.clone()))
+        .batch_size(batch_size)
+        .build(valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use comms::Parquet;
+    use geo::wkt;
+
+    use super::*;
+    use crate::map_match::fixtures;
+
+    #[test]
+    fn vocabulary_round_trip() {
+        let vocabulary = RoadVocabulary::new(&[7, 3, 7, 9]);
+        assert_eq!(vocabulary.num_tokens(), 4);
+        assert_eq!(vocabulary.token(3), Some(2));
+        assert_eq!(vocabulary.road(2), Some(3));
+        assert_eq!(vocabulary.road(PADDING_TOKEN), None);
+        assert_eq!(vocabulary.token(8), None);
+    }
+
+    #[test]
+    fn encodes_heading_in_driving_direction() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let vocabulary = RoadVocabulary::new(roads.iter().map(|(r, _, _)| &r.id));
+
+        let (tokens, east) = encode(&[0, 1, 2], &vocabulary, &network);
+        assert_eq!(tokens, vec![1, 2, 3]);
+        assert!(east.iter().all(|f| (f[3] - 1.).abs() < 1e-3));
+        assert!((east[0][0] - 0.121).abs() < 1e-3, "about 121 meters");
+        assert_eq!(east[0][1], 0.5);
+
+        let (_, west) = encode(&[2, 1, 0, 42], &vocabulary, &network);
+        assert_eq!(west.len(), 3, "unknown roads are skipped");
+        assert!(west.iter().all(|f| (f[3] + 1.).abs() < 1e-3));
+    }
+
+    #[test]
+    fn splits_whole_routes() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let vocabulary = RoadVocabulary::new(roads.iter().map(|(r, _, _)| &r.id));
+        let routes = vec![vec![0, 1, 2], vec![2, 1], vec![1, 2], vec![3]];
+
+        let config = DatasetConfig::new().with_valid_fraction(0.5);
+        let (train, valid) = SequenceDataset::split(&routes, &vocabulary, &network, &config);
+        assert_eq!(train.len() + valid.len(), 4);
+        let starts = |d: &SequenceDataset| d.iter().map(|i| i.tokens[0]).collect::<Vec<_>>();
+        assert!(
+            !starts(&train).contains(&1) || !starts(&valid).contains(&1),
+            "prefixes of [0, 1, 2] should not be in both"
+        );
+
+        let config = DatasetConfig::new()
+            .with_target(Target::Destination)
+            .with_max_length(1);
+        let dataset = SequenceDataset::from_routes(&routes[..1], &vocabulary, &network, &config);
+        let items: Vec<_> = dataset.iter().collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1].tokens, vec![2]);
+        assert!(items.iter().all(|i| i.target == 3));
+    }
+
+    #[test]
+    fn reads_trajectories_from_parquet() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+        let vocabulary = RoadVocabulary::new(roads.iter().map(|(r, _, _)| &r.id));
+
+        let trajectories = Trajectories {
+            id: vec![1, 2],
+            geom: vec![
+                wkt! {LINESTRING(10.0005 57.00005, 10.0055 57.00005)},
+                wkt! {LINESTRING(10.0055 57.00005, 10.0005 57.00005)},
+            ],
+        };
+        let bytes = trajectories.to_parquet().expect("should write");
+        let trajectories = Trajectories::from_parquet(bytes).expect("should read");
+
+        let config = DatasetConfig::new().with_valid_fraction(0.);
+        let (train, valid) = SequenceDataset::from_trajectories(
+            &trajectories,
+            &index,
+            &network,
+            &vocabulary,
+            &MatchConfig::default(),
+            &config,
+        );
+        assert_eq!(train.len(), 4);
+        assert!(valid.is_empty());
+    }
+}
@@ -0,0 +1,234 @@
+use burn::{
+    nn::{Embedding, EmbeddingConfig},
+    optim::{AdamConfig, GradientsParams, Optimizer},
+    prelude::*,
+    tensor::{activation::log_sigmoid, backend::AutodiffBackend, DataError},
+};
+use rand::{
+    distr::{weighted::WeightedIndex, Distribution},
+    rngs::StdRng,
+    seq::SliceRandom,
+    SeedableRng,
+};
+use thiserror::Error;
+
+use crate::{Id, RoadEmbeddings};
+
+use super::{RoadVocabulary, PADDING_TOKEN};
+
+#[derive(Debug, Error)]
+pub enum EmbeddingError {
+    #[error("the embeddings have {0} dimensions, but the model embeds roads in {1}")]
+    Dimensions(usize, usize),
+    #[error("could not read the embedding weights: {0:?}")]
+    Data(DataError),
+}
+
+// `DataError` does not implement `Error`, so it cannot be a `#[from]` source
+impl From<DataError> for EmbeddingError {
+    fn from(value: DataError) -> Self {
+        Self::Data(value)
+    }
+}
+
+/// Learns road embeddings from walks like word2vec learns word embeddings from sentences,
+/// by predicting the roads near each road with negative sampling.
+#[derive(Module, Debug)]
+pub struct SkipGram<B: Backend> {
+    input: Embedding<B>,
+    output: Embedding<B>,
+}
+
+#[derive(Config)]
+pub struct SkipGramConfig {
+    pub optimizer: AdamConfig,
+    #[config(default = 64)]
+    pub embedding_size: usize,
+    /// Roads up to this many steps before or after a road in a walk are its context
+    #[config(default = 5)]
+    pub window: usize,
+    /// Number of random roads contrasted with each context road
+    #[config(default = 5)]
+    pub negatives: usize,
+    #[config(default = 5)]
+    pub num_epochs: usize,
+    #[config(default = 256)]
+    pub batch_size: usize,
+    #[config(default = 1.0e-2)]
+    pub learning_rate: f64,
+    #[config(default = 42)]
+    pub seed: u64,
+}
+
+impl SkipGramConfig {
+    /// Returns the initialized model for `num_tokens` tokens, see [`RoadVocabulary::num_tokens`].
+    pub fn init<B: Backend>(&self, num_tokens: usize, device: &B::Device) -> SkipGram<B> {
+        SkipGram {
+            input: EmbeddingConfig::new(num_tokens, self.embedding_size).init(device),
+            output: EmbeddingConfig::new(num_tokens, self.embedding_size).init(device),
+        }
+    }
+}
+
+impl<B: Backend> SkipGram<B> {
+    /// Mean negative log likelihood of the context roads against the negative samples.
+    ///
+    /// # Shapes
+    ///   - Centers `[batch_size]`
+    ///   - Contexts `[batch_size]`
+    ///   - Negatives `[batch_size, negatives]`
+    pub fn forward(
+        &self,
+        centers: Tensor<B, 1, Int>,
+        contexts: Tensor<B, 1, Int>,
+        negatives: Tensor<B, 2, Int>,
+    ) -> Tensor<B, 1> {
+        let [batch_size] = centers.dims();
+        let center = self.input.forward(centers.reshape([batch_size, 1])); // [batch_size, 1, embedding_size]
+        let context = self.output.forward(contexts.reshape([batch_size, 1]));
+        let negative = self.output.forward(negatives); // [batch_size, negatives, embedding_size]
+
+        let center = center.swap_dims(1, 2);
+        let positive = log_sigmoid(context.matmul(center.clone())).sum_dim(1);
+        let negative = log_sigmoid(negative.matmul(center).neg()).sum_dim(1);
+        (positive + negative).mean().neg()
+    }
+
+    /// The learned vector of every road in `vocabulary`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the weights of the model cannot be read as floats.
+    pub fn embeddings(
+        &self,
+        vocabulary: &RoadVocabulary,
+    ) -> Result<RoadEmbeddings, EmbeddingError> {
+        let [num_tokens, embedding_size] = self.input.weight.dims();
+        let values: Vec<f32> = self
+            .input
+            .weight
+            .val()
+            .into_data()
+            .convert::<f32>()
+            .to_vec()?;
+        let mut embeddings = RoadEmbeddings::default();
+        for (token, vector) in values.chunks(embedding_size).enumerate().take(num_tokens) {
+            if let Some(id) = vocabulary.road(token as u32) {
+                embeddings.push(id, vector);
+            }
+        }
+        Ok(embeddings)
+    }
+}
+
+/// Trains embeddings of the roads in `vocabulary` from walks over the road network, e.g. [`random_walks`](crate::random_walks).
+///
+/// Negative samples are drawn in proportion to how often roads are walked, raised to the power of 3/4 as in word2vec.
+/// Training is deterministic on the CPU. Roads that are never walked keep their random initial vector.
+///
+/// # Errors
+///
+/// This function will return an error if the learned vectors cannot be read, see [`SkipGram::embeddings`].
+pub fn train_embeddings<B: AutodiffBackend>(
+    walks: &[Vec<Id>],
+    vocabulary: &RoadVocabulary,
+    config: &SkipGramConfig,
+    device: &B::Device,
+) -> Result<RoadEmbeddings, EmbeddingError> {
+    B::seed(config.seed);
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut model = config.init::<B>(vocabulary.num_tokens(), device);
+    let mut optimizer = config.optimizer.init();
+
+    let walks: Vec<Vec<u32>> = walks
+        .iter()
+        .map(|walk| walk.iter().filter_map(|id| vocabulary.token(*id)).collect())
+        .collect();
+    let mut pairs: Vec<(u32, u32)> = walks
+        .iter()
+        .flat_map(|walk| {
+            (0..walk.len()).flat_map(move |i| {
+                let context =
+                    i.saturating_sub(config.window)..(i + config.window + 1).min(walk.len());
+                context
+                    .filter(move |j| *j != i)
+                    .map(move |j| (walk[i], walk[j]))
+            })
+        })
+        .collect();
+
+    let mut counts = vec![0.; vocabulary.num_tokens()];
+    walks
+        .iter()
+        .flatten()
+        .for_each(|token| counts[*token as usize] += 1.);
+    counts[PADDING_TOKEN as usize] = 0.;
+    let Ok(noise) = WeightedIndex::new(counts.iter().map(|c: &f64| c.powf(0.75))) else {
+        // nothing was walked
+        return model.embeddings(vocabulary);
+    };
+
+    let ints = |values: Vec<i64>, shape: Vec<usize>| {
+        Tensor::<B, 1, Int>::from_data(TensorData::new(values, [shape.iter().product()]), device)
+    };
+    for _ in 0..config.num_epochs {
+        pairs.shuffle(&mut rng);
+        for batch in pairs.chunks(config.batch_size) {
+            let size = batch.len();
+            let centers = ints(batch.iter().map(|p| i64::from(p.0)).collect(), vec![size]);
+            let contexts = ints(batch.iter().map(|p| i64::from(p.1)).collect(), vec![size]);
+            let negatives = (0..size * config.negatives)
+                .map(|_| noise.sample(&mut rng) as i64)
+                .collect();
+            let negatives =
+                ints(negatives, vec![size, config.negatives]).reshape([size, config.negatives]);
+
+            let loss = model.forward(centers, contexts, negatives);
+            let grads = GradientsParams::from_grads(loss.backward(), &model);
+            model = optimizer.step(config.learning_rate, model, grads);
+        }
+    }
+    model.embeddings(vocabulary)
+}
+
+#[cfg(all(test, feature = "ndarray"))]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::CpuBackend;
+    use super::*;
+    use crate::{map_match::fixtures, random_walks};
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+        let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+        dot / (norm(a) * norm(b))
+    }
+
+    #[test]
+    fn connected_roads_are_similar() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let vocabulary = RoadVocabulary::new(roads.iter().map(|(r, _, _)| &r.id));
+        let walks = random_walks(&network, 20, 6, &mut StdRng::seed_from_u64(7));
+
+        let config = SkipGramConfig::new(AdamConfig::new())
+            .with_embedding_size(8)
+            .with_window(2)
+            .with_num_epochs(20)
+            .with_batch_size(64);
+        let device = Default::default();
+        let embeddings =
+            train_embeddings::<CpuBackend>(&walks, &vocabulary, &config, &device).expect("floats");
+        assert_eq!(embeddings.dimensions(), 8);
+        assert_eq!(
+            embeddings,
+            train_embeddings::<CpuBackend>(&walks, &vocabulary, &config, &device).expect("floats"),
+            "training should be deterministic"
+        );
+
+        // the chain 0 - 1 - 2 is never walked together with the disconnected road 3
+        let vectors = embeddings.vectors();
+        assert!(cosine(&vectors[&0], &vectors[&1]) > cosine(&vectors[&0], &vectors[&3]));
+    }
+}
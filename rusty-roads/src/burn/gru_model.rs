@@ -1,17 +1,27 @@
 use burn::{
     nn::{
         gru::{Gru, GruConfig},
-        pool::{AdaptiveAvgPool2d, AdaptiveAvgPool2dConfig},
-        Dropout, DropoutConfig, Linear, LinearConfig, Relu,
+        loss::CrossEntropyLossConfig,
+        Dropout, DropoutConfig, Embedding, EmbeddingConfig, Linear, LinearConfig, Relu,
     },
     prelude::*,
+    tensor::backend::AutodiffBackend,
+    train::{ClassificationOutput, TrainOutput, TrainStep, ValidStep},
 };
 
+use crate::RoadEmbeddings;
+
+use super::{EmbeddingError, RoadVocabulary, SequenceBatch, Target, NUM_FEATURES};
+
+/// Predicts a class, e.g. the next road, from a sequence of road tokens and their features.
+///
+/// The road tokens are embedded, concatenated with the features of the roads, and passed through two stacked GRUs,
+/// and the hidden state after the last token is classified by two linear layers.
+#[derive(Module, Debug)]
 pub struct Model<B: Backend> {
-    output_dim: usize,
+    embedding: Embedding<B>,
     gru1: Gru<B>,
     gru2: Gru<B>,
-    pool: AdaptiveAvgPool2d,
     dropout: Dropout,
     linear1: Linear<B>,
     linear2: Linear<B>,
@@ -20,8 +30,185 @@ pub struct Model<B: Backend> {
 
 #[derive(Config, Debug)]
 pub struct ModelConfig {
+    /// Number of distinct road tokens, including the padding token [`PADDING_TOKEN`](super::PADDING_TOKEN)
+    pub num_roads: usize,
+    /// Number of classes to predict
+    pub num_classes: usize,
+    /// What the classes are, when they are road tokens
+    #[config(default = "Target::NextRoad")]
+    pub target: Target,
+    #[config(default = 64)]
+    pub embedding_size: usize,
     #[config(default = 128)]
     pub hidden_size: usize,
     #[config(default = "0.5")]
     pub dropout: f64,
-}
\ No newline at end of file
+}
+
+impl ModelConfig {
+    /// Returns the initialized model.
+    pub fn init<B: Backend>(&self, device: &B::Device) -> Model<B> {
+        Model {
+            embedding: EmbeddingConfig::new(self.num_roads, self.embedding_size).init(device),
+            gru1: GruConfig::new(self.embedding_size + NUM_FEATURES, self.hidden_size, true)
+                .init(device),
+            gru2: GruConfig::new(self.hidden_size, self.hidden_size, true).init(device),
+            dropout: DropoutConfig::new(self.dropout).init(),
+            linear1: LinearConfig::new(self.hidden_size, self.hidden_size).init(device),
+            linear2: LinearConfig::new(self.hidden_size, self.num_classes).init(device),
+            activation: Relu::new(),
+        }
+    }
+}
+
+impl<B: Backend> Model<B> {
+    /// Returns the unnormalized log probabilities of each class.
+    ///
+    /// Sequences must be padded on the left, so the last token of every sequence is a road.
+    ///
+    /// # Shapes
+    ///   - Tokens `[batch_size, seq_length]`, where `seq_length` is at least 1
+    ///   - Features `[batch_size, seq_length, NUM_FEATURES]`
+    ///   - Output `[batch_size, num_classes]`
+    pub fn forward(&self, tokens: Tensor<B, 2, Int>, features: Tensor<B, 3>) -> Tensor<B, 2> {
+        let x = self.embedding.forward(tokens); // [batch_size, seq_length, embedding_size]
+        let x = Tensor::cat(vec![x, features], 2);
+        let x = self.gru1.forward(x, None);
+        let x = self.dropout.forward(x);
+        let x = self.gru2.forward(x, None); // [batch_size, seq_length, hidden_size]
+
+        let [batch_size, seq_length, hidden_size] = x.dims();
+        let x = x
+            .slice([0..batch_size, seq_length - 1..seq_length, 0..hidden_size])
+            .reshape([batch_size, hidden_size]);
+
+        let x = self.dropout.forward(x);
+        let x = self.linear1.forward(x);
+        let x = self.activation.forward(x);
+        self.linear2.forward(x) // [batch_size, num_classes]
+    }
+
+    /// Replaces the embedding of every road in both `embeddings` and `vocabulary` with its learned vector,
+    /// e.g. from [`train_embeddings`](super::train_embeddings). Other roads keep their embedding.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the embeddings do not have as many dimensions as the model,
+    /// or if the current embeddings of the model cannot be read as floats.
+    pub fn with_embeddings(
+        mut self,
+        embeddings: &RoadEmbeddings,
+        vocabulary: &RoadVocabulary,
+    ) -> Result<Self, EmbeddingError> {
+        let [num_tokens, embedding_size] = self.embedding.weight.dims();
+        if embeddings.dimensions() != embedding_size {
+            return Err(EmbeddingError::Dimensions(
+                embeddings.dimensions(),
+                embedding_size,
+            ));
+        }
+        let mut values = self
+            .embedding
+            .weight
+            .val()
+            .into_data()
+            .convert::<f32>()
+            .to_vec::<f32>()?;
+        for (id, vector) in embeddings.vectors() {
+            let Some(token) = vocabulary.token(id).map(|t| t as usize) else {
+                continue;
+            };
+            if token < num_tokens {
+                values[token * embedding_size..(token + 1) * embedding_size]
+                    .copy_from_slice(&vector);
+            }
+        }
+        self.embedding.weight = self.embedding.weight.map(|weight| {
+            Tensor::from_data(
+                TensorData::new(values, [num_tokens, embedding_size]),
+                &weight.device(),
+            )
+            .set_require_grad(weight.is_require_grad())
+        });
+        Ok(self)
+    }
+
+    /// Classifies a batch and computes the cross entropy loss against its targets.
+    pub fn forward_classification(&self, batch: SequenceBatch<B>) -> ClassificationOutput<B> {
+        let output = self.forward(batch.tokens, batch.features);
+        let loss = CrossEntropyLossConfig::new()
+            .init(&output.device())
+            .forward(output.clone(), batch.targets.clone());
+        ClassificationOutput::new(loss, output, batch.targets)
+    }
+}
+
+impl<B: AutodiffBackend> TrainStep<SequenceBatch<B>, ClassificationOutput<B>> for Model<B> {
+    fn step(&self, batch: SequenceBatch<B>) -> TrainOutput<ClassificationOutput<B>> {
+        let item = self.forward_classification(batch);
+        TrainOutput::new(self, item.loss.backward(), item)
+    }
+}
+
+impl<B: Backend> ValidStep<SequenceBatch<B>, ClassificationOutput<B>> for Model<B> {
+    fn step(&self, batch: SequenceBatch<B>) -> ClassificationOutput<B> {
+        self.forward_classification(batch)
+    }
+}
+
+#[cfg(all(test, feature = "ndarray"))]
+mod tests {
+    use burn::backend::NdArray;
+
+    use super::*;
+
+    #[test]
+    fn forward_shape() {
+        let device = Default::default();
+        let model = ModelConfig::new(10, 4)
+            .with_embedding_size(8)
+            .with_hidden_size(16)
+            .init::<NdArray>(&device);
+        let tokens = Tensor::<NdArray, 2, Int>::from_ints([[0, 1, 2], [3, 4, 5]], &device);
+        let features = Tensor::zeros([2, 3, NUM_FEATURES], &device);
+        assert_eq!(model.forward(tokens, features).dims(), [2, 4]);
+    }
+
+    #[test]
+    fn loads_embeddings() {
+        let device = Default::default();
+        let vocabulary = RoadVocabulary::new(&[7, 8]);
+        let mut embeddings = RoadEmbeddings::default();
+        embeddings.push(8, &[1., 2.]);
+        embeddings.push(9, &[3., 4.]);
+
+        let model = ModelConfig::new(vocabulary.num_tokens(), 4)
+            .with_embedding_size(2)
+            .init::<NdArray>(&device);
+        let before = model
+            .embedding
+            .weight
+            .val()
+            .into_data()
+            .to_vec::<f32>()
+            .expect("floats");
+        let model = model
+            .with_embeddings(&embeddings, &vocabulary)
+            .expect("same size");
+        let after = model
+            .embedding
+            .weight
+            .val()
+            .into_data()
+            .to_vec::<f32>()
+            .expect("floats");
+        assert_eq!(after[..4], before[..4]);
+        assert_eq!(after[4..], [1., 2.]);
+
+        let wrong = ModelConfig::new(vocabulary.num_tokens(), 4)
+            .with_embedding_size(3)
+            .init::<NdArray>(&device)
+            .with_embeddings(&embeddings, &vocabulary);
+        assert!(matches!(wrong, Err(EmbeddingError::Dimensions(2, 3))));
+    }
+}
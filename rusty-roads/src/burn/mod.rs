@@ -0,0 +1,26 @@
+//! Learned models of road sequences, built with [`burn`](https://burn.dev).
+//!
+//! Enable the `ndarray` feature to train and run models on the CPU, or `wgpu` to use the GPU.
+
+mod gru_model;
+pub use gru_model::*;
+mod training;
+pub use training::*;
+mod dataset;
+pub use dataset::*;
+mod checkpoint;
+pub use checkpoint::*;
+mod predict;
+pub use predict::*;
+mod mode_model;
+pub use mode_model::*;
+mod embedding;
+pub use embedding::*;
+
+/// Backend that trains on the CPU, for machines without a GPU
+#[cfg(feature = "ndarray")]
+pub type CpuBackend = burn::backend::Autodiff<burn::backend::NdArray>;
+
+/// Backend that trains on the GPU
+#[cfg(feature = "wgpu")]
+pub type GpuBackend = burn::backend::Autodiff<burn::backend::Wgpu>;
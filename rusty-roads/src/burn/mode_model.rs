@@ -0,0 +1,169 @@
+use burn::{
+    nn::{loss::CrossEntropyLossConfig, Linear, LinearConfig, Relu},
+    optim::{AdamConfig, GradientsParams, Optimizer},
+    prelude::*,
+    tensor::{activation::softmax, backend::AutodiffBackend, DataError},
+};
+
+use crate::{ModeClassification, ModeFeatures, TransportMode, NUM_MODE_FEATURES};
+
+/// Classifies the [`TransportMode`] of a trip from its [`ModeFeatures`],
+/// as a learned alternative to [`classify_mode`](crate::classify_mode).
+#[derive(Module, Debug)]
+pub struct ModeModel<B: Backend> {
+    linear1: Linear<B>,
+    linear2: Linear<B>,
+    activation: Relu,
+}
+
+#[derive(Config, Debug)]
+pub struct ModeModelConfig {
+    #[config(default = 16)]
+    pub hidden_size: usize,
+}
+
+impl ModeModelConfig {
+    /// Returns the initialized model.
+    pub fn init<B: Backend>(&self, device: &B::Device) -> ModeModel<B> {
+        ModeModel {
+            linear1: LinearConfig::new(NUM_MODE_FEATURES, self.hidden_size).init(device),
+            linear2: LinearConfig::new(self.hidden_size, TransportMode::ALL.len()).init(device),
+            activation: Relu::new(),
+        }
+    }
+}
+
+impl<B: Backend> ModeModel<B> {
+    /// Returns the unnormalized log probabilities of each mode, in the order of [`TransportMode::ALL`].
+    ///
+    /// # Shapes
+    ///   - Features `[batch_size, NUM_MODE_FEATURES]`
+    ///   - Output `[batch_size, 3]`
+    pub fn forward(&self, features: Tensor<B, 2>) -> Tensor<B, 2> {
+        let x = self.linear1.forward(features);
+        let x = self.activation.forward(x);
+        self.linear2.forward(x)
+    }
+
+    /// Classifies every trip by its features.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the output of the model cannot be read as floats.
+    pub fn classify(
+        &self,
+        features: &[ModeFeatures],
+    ) -> Result<Vec<ModeClassification>, DataError> {
+        if features.is_empty() {
+            return Ok(vec![]);
+        }
+        let device = self.linear1.weight.device();
+        let output = softmax(self.forward(input(features, &device)), 1);
+        let probabilities: Vec<f64> = output.into_data().convert::<f64>().to_vec()?;
+        Ok(probabilities
+            .chunks(TransportMode::ALL.len())
+            .map(|p| ModeClassification::from_scores([p[0].ln(), p[1].ln(), p[2].ln()]))
+            .collect())
+    }
+}
+
+fn input<B: Backend>(features: &[ModeFeatures], device: &B::Device) -> Tensor<B, 2> {
+    let values: Vec<f32> = features
+        .iter()
+        .flat_map(|f| f.to_array().map(|v| v as f32))
+        .collect();
+    Tensor::from_data(
+        TensorData::new(values, [features.len(), NUM_MODE_FEATURES]),
+        device,
+    )
+}
+
+#[derive(Config)]
+pub struct ModeTrainingConfig {
+    pub model: ModeModelConfig,
+    pub optimizer: AdamConfig,
+    #[config(default = 200)]
+    pub num_epochs: usize,
+    #[config(default = 1.0e-2)]
+    pub learning_rate: f64,
+    #[config(default = 42)]
+    pub seed: u64,
+}
+
+// `AdamConfig` only implements `Display`, which prints it as JSON
+impl std::fmt::Debug for ModeTrainingConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ModeTrainingConfig")
+            .field("model", &self.model)
+            .field("optimizer", &format_args!("{}", self.optimizer))
+            .field("num_epochs", &self.num_epochs)
+            .field("learning_rate", &self.learning_rate)
+            .field("seed", &self.seed)
+            .finish()
+    }
+}
+
+/// Trains a new [`ModeModel`] on trips with known modes, using all of them in every step.
+pub fn train_mode_model<B: AutodiffBackend>(
+    config: &ModeTrainingConfig,
+    device: &B::Device,
+    examples: &[(ModeFeatures, TransportMode)],
+) -> ModeModel<B> {
+    B::seed(config.seed);
+    let mut model = config.model.init::<B>(device);
+    let mut optimizer = config.optimizer.init();
+    if examples.is_empty() {
+        return model;
+    }
+
+    let features: Vec<_> = examples.iter().map(|(f, _)| *f).collect();
+    let targets: Vec<i64> = examples.iter().map(|(_, m)| m.index() as i64).collect();
+    let targets =
+        Tensor::<B, 1, Int>::from_data(TensorData::new(targets, [examples.len()]), device);
+    let loss = CrossEntropyLossConfig::new().init(device);
+    for _ in 0..config.num_epochs {
+        let output = model.forward(input(&features, device));
+        let grads = loss.forward(output, targets.clone()).backward();
+        let grads = GradientsParams::from_grads(grads, &model);
+        model = optimizer.step(config.learning_rate, model, grads);
+    }
+    model
+}
+
+#[cfg(all(test, feature = "ndarray"))]
+mod tests {
+    use super::super::CpuBackend;
+    use super::*;
+
+    #[test]
+    fn learns_modes_from_speed() {
+        let example = |speed: f64, mode| {
+            let features = ModeFeatures {
+                median_speed: speed,
+                p85_speed: speed * 1.2,
+                ..Default::default()
+            };
+            (features, mode)
+        };
+        let examples: Vec<_> = [1., 1.5, 2.]
+            .map(|s| example(s, TransportMode::Walk))
+            .into_iter()
+            .chain([4., 5., 6.].map(|s| example(s, TransportMode::Bicycle)))
+            .chain([12., 18., 25.].map(|s| example(s, TransportMode::Car)))
+            .collect();
+        let config =
+            ModeTrainingConfig::new(ModeModelConfig::new(), AdamConfig::new()).with_num_epochs(500);
+        assert!(format!("{config:?}").contains("num_epochs: 500"));
+        let model = train_mode_model::<CpuBackend>(&config, &Default::default(), &examples);
+
+        let classified = model
+            .classify(&[
+                example(1.2, TransportMode::Walk).0,
+                example(20., TransportMode::Car).0,
+            ])
+            .expect("floats");
+        assert_eq!(classified[0].mode, TransportMode::Walk);
+        assert_eq!(classified[1].mode, TransportMode::Car);
+        assert!(classified.iter().all(|c| c.confidence > 0.5));
+    }
+}
@@ -0,0 +1,215 @@
+use std::path::Path;
+
+use burn::{data::dataloader::batcher::Batcher, prelude::*, tensor::activation::softmax};
+use petgraph::matrix_graph::IndexType;
+
+use crate::{Id, RoadNetwork};
+
+use super::{
+    check_vocabulary, encode, load_model, CheckpointError, Model, ModelConfig, RoadVocabulary,
+    SequenceBatcher, SequenceItem, Target, PADDING_TOKEN,
+};
+
+/// A road and how likely it is to be driven next, or to be the destination.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Prediction {
+    pub road: Id,
+    pub probability: f64,
+}
+
+/// Predicts where a partial trajectory goes with a trained [`Model`].
+///
+/// Whether the predictions are next roads or destinations depends on the [`Target`] the model was trained for.
+#[derive(Debug)]
+pub struct Predictor<B: Backend> {
+    model: Model<B>,
+    vocabulary: RoadVocabulary,
+    target: Target,
+    batcher: SequenceBatcher<B>,
+}
+
+impl<B: Backend> Predictor<B> {
+    /// Predicts with `model`, initialized from `config` and trained with `vocabulary`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the vocabulary does not fit the model, see [`check_vocabulary`].
+    pub fn new(
+        model: Model<B>,
+        config: &ModelConfig,
+        vocabulary: RoadVocabulary,
+        device: &B::Device,
+    ) -> Result<Self, CheckpointError> {
+        check_vocabulary(config, &vocabulary)?;
+        Ok(Self {
+            model,
+            vocabulary,
+            target: config.target,
+            batcher: SequenceBatcher::new(device.clone()),
+        })
+    }
+
+    /// Loads a model saved with [`save_model`](super::save_model) from the checkpoint directory `dir`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the checkpoint cannot be loaded, see [`load_model`].
+    pub fn load(dir: &Path, device: &B::Device) -> Result<Self, CheckpointError> {
+        let (model, config, vocabulary) = load_model(dir, device)?;
+        Self::new(model, &config, vocabulary, device)
+    }
+
+    /// The `k` most probable roads after the roads with the given ids, most probable first.
+    ///
+    /// Roads missing from the vocabulary or `network` are skipped, see [`encode`].
+    /// Returns no predictions if none of the roads are known.
+    pub fn predict<Idx: IndexType>(
+        &self,
+        roads: &[Id],
+        network: &RoadNetwork<Idx>,
+        k: usize,
+    ) -> Vec<Prediction> {
+        let (tokens, features) = encode(roads, &self.vocabulary, network);
+        if tokens.is_empty() {
+            return vec![];
+        }
+        let item = SequenceItem {
+            tokens,
+            features,
+            target: PADDING_TOKEN,
+        };
+        let Some(probabilities) = self.probabilities(vec![item]).pop() else {
+            return vec![];
+        };
+        let mut predictions: Vec<_> = probabilities
+            .into_iter()
+            .enumerate()
+            .filter_map(|(token, probability)| {
+                Some(Prediction {
+                    road: self.vocabulary.road(token as u32)?,
+                    probability,
+                })
+            })
+            .collect();
+        predictions.sort_by(|fst, snd| snd.probability.total_cmp(&fst.probability));
+        predictions.truncate(k);
+        predictions
+    }
+
+    /// How predictable the trip along `route` is, as the mean probability the model gives the actual [`Target`]
+    /// it was trained for after each prefix of the route, between 0 and 1.
+    ///
+    /// A predictable trip is easier to re-identify, so a high value suggests it should not be released as is.
+    /// Returns [`None`] if the route has fewer than two known roads.
+    pub fn predictability<Idx: IndexType>(
+        &self,
+        route: &[Id],
+        network: &RoadNetwork<Idx>,
+    ) -> Option<f64> {
+        let (tokens, features) = encode(route, &self.vocabulary, network);
+        let destination = *tokens.last()?;
+        let items: Vec<_> = (1..tokens.len())
+            .map(|end| SequenceItem {
+                tokens: tokens[..end].to_vec(),
+                features: features[..end].to_vec(),
+                target: match self.target {
+                    Target::NextRoad => tokens[end],
+                    Target::Destination => destination,
+                },
+            })
+            .collect();
+        if items.is_empty() {
+            return None;
+        }
+
+        let targets: Vec<_> = items.iter().map(|i| i.target as usize).collect();
+        let total: f64 = self
+            .probabilities(items)
+            .iter()
+            .zip(&targets)
+            .map(|(p, target)| p.get(*target).copied().unwrap_or(0.))
+            .sum();
+        Some(total / targets.len() as f64)
+    }
+
+    /// Probability of every token after each of the sequences in `items`.
+    fn probabilities(&self, items: Vec<SequenceItem>) -> Vec<Vec<f64>> {
+        let batch = self.batcher.batch(items);
+        let output = softmax(self.model.forward(batch.tokens, batch.features), 1);
+        let [_, num_classes] = output.dims();
+        let values: Vec<f64> = output
+            .into_data()
+            .convert::<f64>()
+            .to_vec()
+            .expect("the output of the model should be floats");
+        values
+            .chunks(num_classes)
+            .map(|chunk| chunk.to_vec())
+            .collect()
+    }
+}
+
+#[cfg(all(test, feature = "ndarray"))]
+mod tests {
+    use burn::backend::NdArray;
+
+    use super::super::{save_model, ModelConfig};
+    use super::*;
+    use crate::map_match::fixtures;
+
+    #[test]
+    fn loads_checkpoint_and_predicts() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let vocabulary = RoadVocabulary::new(roads.iter().map(|(r, _, _)| &r.id));
+        let config = ModelConfig::new(vocabulary.num_tokens(), vocabulary.num_tokens())
+            .with_embedding_size(4)
+            .with_hidden_size(8);
+
+        let device = Default::default();
+        let model = config.init::<NdArray>(&device);
+        let dir = tempfile::tempdir().expect("should create temp dir");
+        save_model(dir.path(), model.clone(), &config, &vocabulary).expect("should save");
+        let predictor = Predictor::<NdArray>::load(dir.path(), &device).expect("should load");
+        let original =
+            Predictor::new(model, &config, vocabulary.clone(), &device).expect("vocabulary fits");
+
+        let predictions = predictor.predict(&[0, 1], &network, 3);
+        assert_eq!(predictions, original.predict(&[0, 1], &network, 3));
+        assert_eq!(predictions.len(), 3);
+        assert!(predictions
+            .windows(2)
+            .all(|w| w[0].probability >= w[1].probability));
+
+        let predictability = predictor
+            .predictability(&[0, 1, 2], &network)
+            .expect("route is known");
+        assert!((0.0..=1.0).contains(&predictability));
+        assert_eq!(predictor.predictability(&[0], &network), None);
+        assert!(predictor.predict(&[42], &network, 3).is_empty());
+
+        let destinations = config.clone().with_target(Target::Destination);
+        save_model(
+            dir.path(),
+            destinations.init::<NdArray>(&device),
+            &destinations,
+            &vocabulary,
+        )
+        .expect("should save");
+        let predictor = Predictor::<NdArray>::load(dir.path(), &device).expect("should load");
+        assert_eq!(predictor.target, Target::Destination);
+
+        let wrong = ModelConfig::new(vocabulary.num_tokens(), 4);
+        save_model(
+            dir.path(),
+            wrong.init::<NdArray>(&device),
+            &wrong,
+            &vocabulary,
+        )
+        .expect("should save");
+        assert!(matches!(
+            Predictor::<NdArray>::load(dir.path(), &device),
+            Err(CheckpointError::ClassMismatch(_, 4))
+        ));
+    }
+}
@@ -0,0 +1,323 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use burn::{
+    data::{
+        dataloader::{batcher::Batcher, DataLoader, DataLoaderBuilder},
+        dataset::Dataset,
+    },
+    module::AutodiffModule,
+    optim::{AdamConfig, GradientsParams, Optimizer},
+    prelude::*,
+    tensor::backend::AutodiffBackend,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Model, ModelConfig, NUM_FEATURES};
+
+/// Token used to pad sequences to the same length, which no road may use
+pub const PADDING_TOKEN: u32 = 0;
+
+/// A sequence of road tokens, the features of each road, and the class it should be classified as.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequenceItem {
+    pub tokens: Vec<u32>,
+    /// Same length as `tokens`, see [`encode`](super::encode)
+    pub features: Vec<[f32; NUM_FEATURES]>,
+    pub target: u32,
+}
+
+/// Sequences of road tokens and features padded on the left to the same length, and their targets.
+#[derive(Debug, Clone)]
+pub struct SequenceBatch<B: Backend> {
+    /// `[batch_size, seq_length]`
+    pub tokens: Tensor<B, 2, Int>,
+    /// `[batch_size, seq_length, NUM_FEATURES]`
+    pub features: Tensor<B, 3>,
+    /// `[batch_size]`
+    pub targets: Tensor<B, 1, Int>,
+}
+
+/// Loads [`SequenceBatch`]es for [`evaluate`]
+pub type SequenceLoader<B> = Arc<dyn DataLoader<SequenceBatch<B>>>;
+
+/// Batches [`SequenceItem`]s on a device, padding them on the left with [`PADDING_TOKEN`] and zero features.
+#[derive(Debug, Clone)]
+pub struct SequenceBatcher<B: Backend> {
+    device: B::Device,
+}
+
+impl<B: Backend> SequenceBatcher<B> {
+    pub fn new(device: B::Device) -> Self {
+        Self { device }
+    }
+}
+
+impl<B: Backend> Batcher<SequenceItem, SequenceBatch<B>> for SequenceBatcher<B> {
+    fn batch(&self, items: Vec<SequenceItem>) -> SequenceBatch<B> {
+        // an empty sequence is padded to a single token, so the model always has a last token to classify
+        let seq_length = items
+            .iter()
+            .map(|i| i.tokens.len())
+            .max()
+            .unwrap_or(0)
+            .max(1);
+        let tokens: Vec<i64> = items
+            .iter()
+            .flat_map(|item| {
+                let padding = seq_length - item.tokens.len();
+                std::iter::repeat_n(PADDING_TOKEN, padding)
+                    .chain(item.tokens.iter().copied())
+                    .map(i64::from)
+            })
+            .collect();
+        let features: Vec<f32> = items
+            .iter()
+            .flat_map(|item| {
+                let padding = seq_length - item.features.len();
+                std::iter::repeat_n([0.; NUM_FEATURES], padding)
+                    .chain(item.features.iter().copied())
+            })
+            .flatten()
+            .collect();
+        let targets: Vec<i64> = items.iter().map(|i| i64::from(i.target)).collect();
+
+        SequenceBatch {
+            tokens: Tensor::from_data(
+                TensorData::new(tokens, [items.len(), seq_length]),
+                &self.device,
+            ),
+            features: Tensor::from_data(
+                TensorData::new(features, [items.len(), seq_length, NUM_FEATURES]),
+                &self.device,
+            ),
+            targets: Tensor::from_data(TensorData::new(targets, [items.len()]), &self.device),
+        }
+    }
+}
+
+#[derive(Config)]
+pub struct TrainingConfig {
+    pub model: ModelConfig,
+    pub optimizer: AdamConfig,
+    #[config(default = 10)]
+    pub num_epochs: usize,
+    #[config(default = 32)]
+    pub batch_size: usize,
+    #[config(default = 1.0e-3)]
+    pub learning_rate: f64,
+    #[config(default = 42)]
+    pub seed: u64,
+}
+
+/// Mean loss and accuracy after an epoch of training.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EpochMetrics {
+    /// Starting from 1
+    pub epoch: usize,
+    pub train_loss: f64,
+    pub valid_loss: f64,
+    /// Fraction of validation sequences classified correctly
+    pub valid_accuracy: f64,
+}
+
+/// Trains a new model on shuffled batches of `train`, and reports its loss and accuracy on `valid` after every epoch.
+///
+/// Unlike burn's `Learner` this needs no terminal or GPU, and runs on any backend, e.g. [`CpuBackend`](super::CpuBackend).
+/// Training is deterministic on the CPU, as every epoch is shuffled and seeded with `config.seed` plus its number.
+/// Use [`train_checkpointed`](super::train_checkpointed) to be able to resume interrupted training.
+pub fn train<B, D>(
+    config: &TrainingConfig,
+    device: &B::Device,
+    train: D,
+    valid: SequenceLoader<B::InnerBackend>,
+) -> (Model<B>, Vec<EpochMetrics>)
+where
+    B: AutodiffBackend,
+    D: Dataset<SequenceItem> + 'static,
+{
+    B::seed(config.seed);
+    let model = config.model.init::<B>(device);
+    let optimizer = config.optimizer.init();
+    let fitted = fit(
+        config,
+        device,
+        train,
+        valid,
+        model,
+        optimizer,
+        vec![],
+        |_, _, _| Ok::<_, Infallible>(()),
+    );
+    match fitted {
+        Ok((model, _, metrics)) => (model, metrics),
+        Err(never) => match never {},
+    }
+}
+
+/// Trains `model` for the epochs after those in `metrics`, calling `after_epoch` with the model,
+/// the optimizer and the metrics so far after every epoch.
+///
+/// Every epoch is shuffled and seeded on its own, so training resumed after an epoch continues exactly as if it had not stopped.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn fit<B, D, O, E, F>(
+    config: &TrainingConfig,
+    device: &B::Device,
+    train: D,
+    valid: SequenceLoader<B::InnerBackend>,
+    mut model: Model<B>,
+    mut optimizer: O,
+    mut metrics: Vec<EpochMetrics>,
+    mut after_epoch: F,
+) -> Result<(Model<B>, O, Vec<EpochMetrics>), E>
+where
+    B: AutodiffBackend,
+    D: Dataset<SequenceItem> + 'static,
+    O: Optimizer<Model<B>, B>,
+    F: FnMut(&Model<B>, &O, &[EpochMetrics]) -> Result<(), E>,
+{
+    let train = Arc::new(train);
+    for epoch in metrics.len() + 1..=config.num_epochs {
+        let seed = config.seed.wrapping_add(epoch as u64);
+        B::seed(seed);
+        let loader = DataLoaderBuilder::new(SequenceBatcher::<B>::new(device.clone()))
+            .batch_size(config.batch_size)
+            .shuffle(seed)
+            .build(Arc::clone(&train));
+        let mut train_loss = Mean::default();
+        for batch in loader.iter() {
+            let size = batch.targets.dims()[0];
+            let output = model.forward_classification(batch);
+            train_loss.add(scalar(output.loss.clone()), size);
+
+            let grads = GradientsParams::from_grads(output.loss.backward(), &model);
+            model = optimizer.step(config.learning_rate, model, grads);
+        }
+
+        let (valid_loss, valid_accuracy) = evaluate(&model.valid(), valid.as_ref());
+        metrics.push(EpochMetrics {
+            epoch,
+            train_loss: train_loss.get(),
+            valid_loss,
+            valid_accuracy,
+        });
+        after_epoch(&model, &optimizer, &metrics)?;
+    }
+    Ok((model, optimizer, metrics))
+}
+
+/// Mean loss and accuracy of `model` on the batches of `data`.
+pub fn evaluate<B: Backend>(
+    model: &Model<B>,
+    data: &dyn DataLoader<SequenceBatch<B>>,
+) -> (f64, f64) {
+    let mut loss = Mean::default();
+    let mut correct = 0;
+    let mut total = 0;
+    for batch in data.iter() {
+        let size = batch.targets.dims()[0];
+        let output = model.forward_classification(batch);
+        loss.add(scalar(output.loss), size);
+        correct += output
+            .output
+            .argmax(1)
+            .flatten::<1>(0, 1)
+            .equal(output.targets)
+            .int()
+            .sum()
+            .into_scalar()
+            .elem::<i64>();
+        total += size;
+    }
+    (loss.get(), correct as f64 / total.max(1) as f64)
+}
+
+fn scalar<B: Backend>(tensor: Tensor<B, 1>) -> f64 {
+    tensor.into_scalar().elem::<f64>()
+}
+
+/// Mean of per-batch values weighted by batch size.
+#[derive(Default)]
+struct Mean {
+    sum: f64,
+    count: usize,
+}
+
+impl Mean {
+    fn add(&mut self, value: f64, count: usize) {
+        self.sum += value * count as f64;
+        self.count += count;
+    }
+
+    fn get(&self) -> f64 {
+        self.sum / self.count.max(1) as f64
+    }
+}
+
+#[cfg(all(test, feature = "ndarray"))]
+mod tests {
+    use burn::data::{dataloader::DataLoaderBuilder, dataset::InMemDataset};
+
+    use super::super::CpuBackend;
+    use super::*;
+
+    #[test]
+    fn pads_on_the_left() {
+        let batcher = SequenceBatcher::<CpuBackend>::new(Default::default());
+        let batch = batcher.batch(vec![
+            SequenceItem {
+                tokens: vec![1, 2, 3],
+                features: vec![[1.; NUM_FEATURES]; 3],
+                target: 4,
+            },
+            SequenceItem {
+                tokens: vec![5],
+                features: vec![[2.; NUM_FEATURES]],
+                target: 6,
+            },
+        ]);
+        let tokens = batch.tokens.into_data().to_vec::<i64>().expect("ints");
+        assert_eq!(tokens, vec![1, 2, 3, 0, 0, 5]);
+        assert_eq!(batch.features.dims(), [2, 3, NUM_FEATURES]);
+        let features = batch.features.into_data().to_vec::<f32>().expect("floats");
+        assert_eq!(features[3 * NUM_FEATURES], 0.);
+        assert_eq!(features[5 * NUM_FEATURES], 2.);
+    }
+
+    #[test]
+    fn learns_next_road_on_cpu() {
+        // driving along the chain 1 -> 2 -> 3 -> 4 -> 5, predict the next road
+        let items: Vec<_> = (1..=3)
+            .flat_map(|start| {
+                (start + 1..=4).map(move |end| SequenceItem {
+                    tokens: (start..=end).collect(),
+                    features: vec![[0.; NUM_FEATURES]; (end - start + 1) as usize],
+                    target: end + 1,
+                })
+            })
+            .collect();
+        let config = TrainingConfig::new(
+            ModelConfig::new(6, 6)
+                .with_embedding_size(8)
+                .with_hidden_size(16)
+                .with_dropout(0.),
+            AdamConfig::new(),
+        )
+        .with_num_epochs(60)
+        .with_batch_size(6)
+        .with_learning_rate(1.0e-2);
+
+        let device = Default::default();
+        let valid_loader = DataLoaderBuilder::new(SequenceBatcher::new(device))
+            .batch_size(config.batch_size)
+            .build(InMemDataset::new(items.clone()));
+
+        let (_, metrics) =
+            train::<CpuBackend, _>(&config, &device, InMemDataset::new(items), valid_loader);
+        assert_eq!(metrics.len(), 60);
+        let (first, last) = (metrics[0], metrics[59]);
+        assert!(last.train_loss < first.train_loss);
+        assert_eq!(last.valid_accuracy, 1.);
+    }
+}
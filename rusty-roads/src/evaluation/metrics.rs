@@ -0,0 +1,258 @@
+use std::collections::HashSet;
+
+use geo::line_measures::FrechetDistance;
+use geo::{Distance, Haversine, Length};
+use geo_types::LineString;
+
+use crate::{Id, MatchedPoint, Meter, Queryable, RoadKey, Roads};
+
+/// What a trajectory really did, to compare matched output against.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroundTruth {
+    /// The roads driven, in order
+    pub roads: Vec<Id>,
+    /// The true position of every point of the trajectory
+    pub positions: LineString<f64>,
+}
+
+/// How well a trajectory was matched, compared to its [`GroundTruth`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    /// See [`route_mismatch_fraction`]
+    pub route_mismatch: f64,
+    /// See [`length_weighted_accuracy`]
+    pub length_accuracy: f64,
+    /// Discrete Fréchet distance in meters between the true and the matched positions
+    pub frechet: Meter,
+    /// See [`hausdorff_distance`]
+    pub hausdorff: Meter,
+    /// Distances from the matched to the true position of every point
+    pub errors: ErrorHistogram,
+}
+
+/// Compares the `matched` points and the `matched_roads` driven between them with `truth`,
+/// where `roads` provides the length of the roads.
+///
+/// Roads missing from `roads` count as having no length.
+pub fn evaluate(
+    roads: &Roads,
+    truth: &GroundTruth,
+    matched: &[MatchedPoint],
+    matched_roads: &[Id],
+    bin_width: Meter,
+) -> Evaluation {
+    let positions: LineString<f64> = matched.iter().map(|m| m.point).collect();
+    Evaluation {
+        route_mismatch: route_mismatch_fraction(roads, &truth.roads, matched_roads),
+        length_accuracy: length_weighted_accuracy(roads, &truth.roads, matched_roads),
+        frechet: Haversine.frechet_distance(&truth.positions, &positions),
+        hausdorff: hausdorff_distance(&truth.positions, &positions),
+        errors: ErrorHistogram::new(&point_errors(matched, &truth.positions), bin_width),
+    }
+}
+
+/// Length of the roads wrongly matched plus the length of the true roads that were missed,
+/// relative to the length of the true route, as defined by Newson & Krumm (2009).
+///
+/// 0 is a perfect match, and the fraction can exceed 1 when many wrong roads are matched.
+/// Returns 0 if the true route has no length.
+pub fn route_mismatch_fraction(roads: &Roads, truth: &[Id], matched: &[Id]) -> f64 {
+    let (truth, matched) = (as_set(truth), as_set(matched));
+    let total = total_length(roads, &truth);
+    if total == 0. {
+        return 0.;
+    }
+    let missed = total_length(roads, &truth.difference(&matched).copied().collect());
+    let added = total_length(roads, &matched.difference(&truth).copied().collect());
+    (missed + added) / total
+}
+
+/// Length of the roads in both routes, relative to the length of the longer route.
+///
+/// 1 is a perfect match. Returns 1 if neither route has any length.
+pub fn length_weighted_accuracy(roads: &Roads, truth: &[Id], matched: &[Id]) -> f64 {
+    let (truth, matched) = (as_set(truth), as_set(matched));
+    let longest = total_length(roads, &truth).max(total_length(roads, &matched));
+    if longest == 0. {
+        return 1.;
+    }
+    total_length(roads, &truth.intersection(&matched).copied().collect()) / longest
+}
+
+/// Largest distance in meters from a vertex of either line string to the closest vertex of the other.
+///
+/// Returns 0 if either is empty.
+pub fn hausdorff_distance(a: &LineString<f64>, b: &LineString<f64>) -> Meter {
+    let directed = |from: &LineString<f64>, to: &LineString<f64>| {
+        from.points()
+            .filter_map(|p| {
+                to.points()
+                    .map(|q| Haversine.distance(p, q))
+                    .min_by(f64::total_cmp)
+            })
+            .fold(0., f64::max)
+    };
+    directed(a, b).max(directed(b, a))
+}
+
+/// Distance in meters from every matched point to the true position of the point.
+///
+/// Points beyond the end of the shorter of the two are ignored.
+pub fn point_errors(matched: &[MatchedPoint], truth: &LineString<f64>) -> Vec<Meter> {
+    matched
+        .iter()
+        .zip(truth.points())
+        .map(|(m, p)| Haversine.distance(m.point, p))
+        .collect()
+}
+
+/// Counts of errors in bins of equal width, starting from 0.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorHistogram {
+    pub bin_width: Meter,
+    /// Number of errors in `[i * bin_width, (i + 1) * bin_width)` for every bin `i`
+    pub counts: Vec<usize>,
+    pub mean: Meter,
+    pub max: Meter,
+}
+
+impl ErrorHistogram {
+    /// # Panics
+    ///
+    /// Panics if `bin_width` is not positive, or if an error is negative or not finite.
+    pub fn new(errors: &[Meter], bin_width: Meter) -> ErrorHistogram {
+        assert!(bin_width > 0., "bin width should be positive");
+        assert!(
+            errors.iter().all(|e| e.is_finite() && *e >= 0.),
+            "errors should be finite and non-negative"
+        );
+        let max = errors.iter().copied().fold(0., f64::max);
+        let mut counts = vec![0; errors.len().min(1) + (max / bin_width) as usize];
+        for error in errors {
+            counts[(error / bin_width) as usize] += 1;
+        }
+        ErrorHistogram {
+            bin_width,
+            counts,
+            mean: errors.iter().sum::<f64>() / errors.len().max(1) as f64,
+            max,
+        }
+    }
+
+    /// Smallest error such that at least `quantile` of the errors fall in bins below it, e.g. 0.95 for the 95th percentile.
+    pub fn quantile(&self, quantile: f64) -> Meter {
+        let total: usize = self.counts.iter().sum();
+        let wanted = (quantile.clamp(0., 1.) * total as f64).ceil() as usize;
+        let mut seen = 0;
+        for (bin, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= wanted {
+                return (bin + 1) as f64 * self.bin_width;
+            }
+        }
+        self.counts.len() as f64 * self.bin_width
+    }
+}
+
+fn as_set(roads: &[Id]) -> HashSet<Id> {
+    roads.iter().copied().collect()
+}
+
+fn total_length(roads: &Roads, ids: &HashSet<Id>) -> Meter {
+    ids.iter()
+        .filter_map(|id| roads.find_index(&RoadKey(*id)))
+        .map(|i| Haversine.length(&roads.geom[i]))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::wkt;
+    use geo_types::Point;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::map_match::fixtures;
+    use crate::{
+        gaussian_noise, hmm_match_points_with_config, hmm_match_with_config, sample_along,
+        MatchConfig,
+    };
+
+    fn roads() -> Roads {
+        fixtures::roads()
+            .into_iter()
+            .map(|(road, _, _)| road)
+            .collect()
+    }
+
+    #[test]
+    fn route_metrics() {
+        let roads = roads();
+        assert_eq!(route_mismatch_fraction(&roads, &[0, 1, 2], &[0, 1, 2]), 0.);
+        assert_eq!(length_weighted_accuracy(&roads, &[0, 1, 2], &[0, 1, 2]), 1.);
+
+        // the roads of the chain are equally long, and the parallel road is three times as long
+        let mismatch = route_mismatch_fraction(&roads, &[0, 1, 2], &[0, 3]);
+        assert!((mismatch - 5. / 3.).abs() < 1e-3, "{mismatch}");
+        let accuracy = length_weighted_accuracy(&roads, &[0, 1, 2], &[0, 2]);
+        assert!((accuracy - 2. / 3.).abs() < 1e-3, "{accuracy}");
+    }
+
+    #[test]
+    fn distances_and_errors() {
+        let truth = wkt! {LINESTRING(10.0 57.0, 10.001 57.0, 10.002 57.0)};
+        let matched: Vec<_> = [(10.0, 57.0), (10.001, 57.0001), (10.002, 57.0)]
+            .into_iter()
+            .map(|(x, y)| MatchedPoint {
+                road: 0,
+                point: Point::new(x, y),
+                fraction: 0.,
+                distance: 0.,
+                rank: Some(0),
+            })
+            .collect();
+        let errors = point_errors(&matched, &truth);
+        assert_eq!(errors[0], 0.);
+        assert!((errors[1] - 11.1).abs() < 0.1, "{}", errors[1]);
+
+        let positions: LineString<f64> = matched.iter().map(|m| m.point).collect();
+        assert!((hausdorff_distance(&truth, &positions) - errors[1]).abs() < 1e-9);
+
+        let histogram = ErrorHistogram::new(&errors, 5.);
+        assert_eq!(histogram.counts, vec![2, 0, 1]);
+        assert_eq!(histogram.quantile(0.5), 5.);
+        assert_eq!(histogram.quantile(1.), 15.);
+        assert!(ErrorHistogram::new(&[], 5.).counts.is_empty());
+    }
+
+    #[test]
+    fn evaluates_noisy_matching() {
+        let fixture = fixtures::roads();
+        let network = fixtures::network(&fixture);
+        let index = fixtures::index(&fixture);
+        let truth = GroundTruth {
+            roads: vec![0, 1, 2],
+            positions: sample_along(&wkt! {LINESTRING(10.0 57.0, 10.006 57.0)}, 30.),
+        };
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let noisy = gaussian_noise(&truth.positions, 4., &mut rng);
+        let config = MatchConfig::default();
+        let matched =
+            hmm_match_points_with_config(&noisy, &index, &network, &config).expect("should match");
+        let matched_roads =
+            hmm_match_with_config(&noisy, &index, &network, &config).expect("should match");
+
+        let evaluation = evaluate(&roads(), &truth, &matched, &matched_roads, 5.);
+        assert_eq!(evaluation.route_mismatch, 0.);
+        assert_eq!(evaluation.length_accuracy, 1.);
+        assert_eq!(
+            evaluation.errors.counts.iter().sum::<usize>(),
+            truth.positions.0.len()
+        );
+        assert!(evaluation.errors.mean < 10., "{}", evaluation.errors.mean);
+        assert!(evaluation.frechet >= evaluation.errors.max - 1e-9);
+        assert!(evaluation.hausdorff <= evaluation.frechet + 1e-9);
+    }
+}
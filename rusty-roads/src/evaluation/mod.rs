@@ -0,0 +1,4 @@
+mod metrics;
+pub use metrics::*;
+mod noise;
+pub use noise::*;
@@ -0,0 +1,134 @@
+//! Generators of synthetic trajectories with known ground truth, for comparing matchers quantitatively.
+
+use geo::{Destination, Distance, Haversine, InterpolatePoint};
+use geo_types::{LineString, Point};
+use rand::Rng;
+use rand_distr::{Distribution, Normal};
+
+use crate::Meter;
+
+/// Points every `spacing` meters along `route`, starting at its first point and ending at its last.
+///
+/// # Panics
+///
+/// Panics if `spacing` is not positive.
+pub fn sample_along(route: &LineString<f64>, spacing: Meter) -> LineString<f64> {
+    assert!(spacing > 0., "spacing should be positive");
+    let mut samples = vec![];
+    // distance along the current segment to the next sample
+    let mut next = 0.;
+    for line in route.lines() {
+        let (start, end) = (line.start_point(), line.end_point());
+        let length = Haversine.distance(start, end);
+        while next <= length {
+            samples.push(match length > 0. {
+                true => Haversine.point_at_ratio_between(start, end, next / length),
+                false => start,
+            });
+            next += spacing;
+        }
+        next -= length;
+    }
+    let last = route.points().next_back();
+    if samples.last() != last.as_ref() {
+        samples.extend(last);
+    }
+    samples.into_iter().collect()
+}
+
+/// Moves every point in a random direction, such that its offsets east and north are normally distributed with
+/// standard deviation `sigma` meters, as is common for GPS noise.
+///
+/// # Panics
+///
+/// Panics if `sigma` is negative or not finite.
+pub fn gaussian_noise<R: Rng + ?Sized>(
+    path: &LineString<f64>,
+    sigma: Meter,
+    rng: &mut R,
+) -> LineString<f64> {
+    let normal = Normal::new(0., sigma).expect("sigma should be finite and non-negative");
+    path.points()
+        .map(|p| {
+            let (east, north) = (normal.sample(rng), normal.sample(rng));
+            Haversine.destination(p, east.atan2(north).to_degrees(), east.hypot(north))
+        })
+        .collect()
+}
+
+/// Moves every point `distance` meters in a random direction with the given `probability`, to simulate e.g. multipath errors.
+///
+/// # Panics
+///
+/// Panics if `probability` is not between 0 and 1.
+pub fn with_outliers<R: Rng + ?Sized>(
+    path: &LineString<f64>,
+    probability: f64,
+    distance: Meter,
+    rng: &mut R,
+) -> LineString<f64> {
+    path.points()
+        .map(|p| match rng.random_bool(probability) {
+            true => Haversine.destination(p, rng.random_range(0.0..360.0), distance),
+            false => p,
+        })
+        .collect()
+}
+
+/// Keeps every `step`th point, always including the first and the last, to simulate sparse sampling.
+///
+/// # Panics
+///
+/// Panics if `step` is 0.
+pub fn downsample(path: &LineString<f64>, step: usize) -> LineString<f64> {
+    let mut points: Vec<Point> = path.points().step_by(step).collect();
+    let last = path.points().next_back();
+    if points.last() != last.as_ref() {
+        points.extend(last);
+    }
+    points.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::wkt;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn samples_evenly() {
+        // about 121 meters per road
+        let route = wkt! {LINESTRING(10.000 57.0, 10.002 57.0, 10.004 57.0)};
+        let samples = sample_along(&route, 50.);
+        assert_eq!(samples.0.len(), 6);
+        assert_eq!(samples.0.first(), route.0.first());
+        assert_eq!(samples.0.last(), route.0.last());
+        for (a, b) in samples.points().zip(samples.points().skip(1)).take(4) {
+            assert!((Haversine.distance(a, b) - 50.).abs() < 1e-6);
+        }
+        assert_eq!(downsample(&samples, 2).0.len(), 4);
+    }
+
+    #[test]
+    fn noise_has_requested_spread() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let path: LineString<f64> = (0..2000).map(|_| (10.0, 57.0)).collect();
+        let noisy = gaussian_noise(&path, 5., &mut rng);
+        let mean = noisy
+            .points()
+            .map(|p| Haversine.distance(p, Point::new(10.0, 57.0)))
+            .sum::<f64>()
+            / 2000.;
+        // the distance of a point with normally distributed offsets follows a Rayleigh distribution
+        let expected = 5. * (std::f64::consts::PI / 2.).sqrt();
+        assert!((mean - expected).abs() < 0.3, "{mean}");
+
+        let moved = with_outliers(&path, 0.1, 100., &mut rng)
+            .points()
+            .filter(|p| (Haversine.distance(*p, Point::new(10.0, 57.0)) - 100.).abs() < 1e-6)
+            .count();
+        assert!((150..250).contains(&moved), "{moved}");
+    }
+}
@@ -1,2 +1,4 @@
 mod road_network;
 pub use road_network::*;
+mod walks;
+pub use walks::*;
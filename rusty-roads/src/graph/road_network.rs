@@ -4,6 +4,7 @@ use derive_more::Into;
 use geo::Point;
 use petgraph::Direction::{Incoming, Outgoing};
 use petgraph::{matrix_graph::*, visit::EdgeRef};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone)]
 pub struct RoadWithNode<'a> {
@@ -15,11 +16,12 @@ pub struct RoadWithNode<'a> {
 #[allow(type_alias_bounds)]
 type RoadNetworkGraph<'a, Idx: IndexType> = DiMatrix<i32, &'a Road, Option<&'a Road>, Idx>;
 
-type NodeId = i32;
+pub type NodeId = i32;
 
 pub struct RoadNetwork<'a, Idx: IndexType> {
     network: DiMatrix<NodeId, &'a Road, Option<&'a Road>, Idx>,
     bi_map: BiMap<NodeId, NodeIndex<Idx>>,
+    roads: HashMap<Id, RoadWithNode<'a>>,
 }
 
 impl<'a, Idx: IndexType> RoadNetwork<'a, Idx> {
@@ -39,12 +41,14 @@ impl<'a, Idx: IndexType> RoadNetwork<'a, Idx> {
         ); //TODO better error handling
         let mut graph = RoadNetworkGraph::<Idx>::with_capacity(size);
         let mut bi_map = BiHashMap::with_capacity(size);
-        for RoadWithNode {
-            road,
-            source,
-            target,
-        } in roads
-        {
+        let mut by_id = HashMap::with_capacity(size);
+        for road_with_node in roads {
+            by_id.insert(road_with_node.road.id, road_with_node.clone());
+            let RoadWithNode {
+                road,
+                source,
+                target,
+            } = road_with_node;
             let s = match bi_map.get_by_left(&source) {
                 Some(e) => *e,
                 None => {
@@ -78,6 +82,7 @@ impl<'a, Idx: IndexType> RoadNetwork<'a, Idx> {
         Some(RoadNetwork {
             network: graph,
             bi_map,
+            roads: by_id,
         })
     }
 
@@ -133,6 +138,27 @@ impl<'a, Idx: IndexType> RoadNetwork<'a, Idx> {
         Some((NonNegativef64::try_from(total_cost)?, roads))
     }
 
+    /// Finds the road with the given id, along with the nodes it connects
+    pub fn road(&self, id: Id) -> Option<&RoadWithNode<'a>> {
+        self.roads.get(&id)
+    }
+
+    /// Every road in the network, in no particular order
+    pub fn roads(&self) -> impl Iterator<Item = &RoadWithNode<'a>> {
+        self.roads.values()
+    }
+
+    /// The roads that can be driven away from the node `id`, each with the node it leads to
+    pub fn roads_from(&self, id: NodeId) -> Vec<(&'a Road, NodeId)> {
+        let Some(a) = self.bi_map.get_by_left(&id) else {
+            return vec![];
+        };
+        self.network
+            .edges_directed(*a, Outgoing)
+            .filter_map(|(_, b, road)| Some((*road, *self.bi_map.get_by_right(&b)?)))
+            .collect()
+    }
+
     pub fn point_from_node(&self, id: NodeId) -> Option<Point> {
         let a = self.bi_map.get_by_left(&id)?;
         let io = self
@@ -0,0 +1,82 @@
+use petgraph::matrix_graph::IndexType;
+use rand::{seq::IndexedRandom, Rng};
+
+use crate::{Direction, Id, RoadNetwork};
+
+/// Random walks over the roads of `network`, for learning road embeddings in the style of DeepWalk.
+///
+/// `walks_per_road` walks of up to `walk_length` roads start on every road, in a random direction it may be driven.
+/// Each step continues onto a random road leaving the end of the current one, avoiding U-turns unless at a dead end.
+/// A walk stops early if no road leaves its end.
+///
+/// Walks are ordered by the id of their first road, so they only depend on `rng`.
+pub fn random_walks<Idx, R>(
+    network: &RoadNetwork<Idx>,
+    walks_per_road: usize,
+    walk_length: usize,
+    rng: &mut R,
+) -> Vec<Vec<Id>>
+where
+    Idx: IndexType,
+    R: Rng + ?Sized,
+{
+    let mut starts: Vec<_> = network.roads().collect();
+    starts.sort_by_key(|r| r.road.id);
+
+    let mut walks = Vec::with_capacity(starts.len() * walks_per_road);
+    for start in starts {
+        for _ in 0..walks_per_road {
+            let mut end = match start.road.direction {
+                Direction::Forward => start.target,
+                Direction::Backward => start.source,
+                Direction::Bidirectional => match rng.random() {
+                    true => start.target,
+                    false => start.source,
+                },
+            };
+            let mut walk = vec![start.road.id];
+            while walk.len() < walk_length {
+                let current = walk[walk.len() - 1];
+                let next = network.roads_from(end);
+                let onwards: Vec<_> = next.iter().filter(|(r, _)| r.id != current).collect();
+                let Some((road, node)) = (match onwards.is_empty() {
+                    true => next.choose(rng),
+                    false => onwards.choose(rng).copied(),
+                }) else {
+                    break;
+                };
+                walk.push(road.id);
+                end = *node;
+            }
+            walks.push(walk);
+        }
+    }
+    walks
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::map_match::fixtures;
+
+    #[test]
+    fn walks_along_connected_roads() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let walks = random_walks(&network, 3, 5, &mut StdRng::seed_from_u64(1));
+        assert_eq!(walks.len(), 12);
+        assert!(walks.iter().all(|w| w.len() == 5));
+
+        let connected = |a: Id, b: Id| a.abs_diff(b) <= 1 && a.max(b) <= 2 || a == b;
+        for walk in &walks {
+            assert!(walk.windows(2).all(|w| connected(w[0], w[1])), "{walk:?}");
+        }
+        // the disconnected road can only be walked back and forth
+        assert!(walks[9..].iter().flatten().all(|id| *id == 3));
+
+        let again = random_walks(&network, 3, 5, &mut StdRng::seed_from_u64(1));
+        assert_eq!(walks, again);
+    }
+}
@@ -1,6 +1,10 @@
 mod road_index;
 pub use road_index::*;
+mod shared_road_index;
 use rstar::{primitives::GeomWithData, PointDistance, RTreeObject};
+pub use shared_road_index::*;
+mod trajectory_index;
+pub use trajectory_index::*;
 
 use crate::Id;
 use geo_types::Point;
@@ -13,3 +17,18 @@ where
     fn nearest_neighbor(&self, point: T) -> Option<GeomWithData<U, Id>>;
     fn nearest_neighbor_road(&self, point: T, id: Id) -> Option<Point>;
 }
+
+impl<T, U, N> NearestNeighbor<T, U> for &N
+where
+    T: RTreeObject + PointDistance,
+    U: RTreeObject + PointDistance,
+    N: NearestNeighbor<T, U>,
+{
+    fn nearest_neighbor(&self, point: T) -> Option<GeomWithData<U, Id>> {
+        (**self).nearest_neighbor(point)
+    }
+
+    fn nearest_neighbor_road(&self, point: T, id: Id) -> Option<Point> {
+        (**self).nearest_neighbor_road(point, id)
+    }
+}
@@ -1,7 +1,8 @@
+use geo::{closest_point::ClosestPoint, Closest};
 use geo_types::{LineString, Point};
 use rstar::{primitives::GeomWithData, RTree, AABB};
 
-use crate::Id;
+use crate::{Id, NearestNeighbor};
 
 #[derive(Debug, Clone)]
 pub struct RoadIndex {
@@ -48,6 +49,23 @@ impl RoadIndex {
     }
 }
 
+impl NearestNeighbor<Point, LineString<f64>> for RoadIndex {
+    fn nearest_neighbor(&self, point: Point) -> Option<GeomWithData<LineString<f64>, Id>> {
+        self.index.nearest_neighbor(&point).cloned()
+    }
+
+    fn nearest_neighbor_road(&self, point: Point, id: Id) -> Option<Point> {
+        let road = self
+            .index
+            .nearest_neighbor_iter(&point)
+            .find(|road| road.data == id)?;
+        match road.geom().closest_point(&point) {
+            Closest::SinglePoint(p) | Closest::Intersection(p) => Some(p),
+            Closest::Indeterminate => None,
+        }
+    }
+}
+
 impl Default for RoadIndex {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,190 @@
+use std::{ops::Deref, sync::Arc};
+
+use arc_swap::ArcSwap;
+use geo_types::{LineString, Point};
+use rstar::primitives::GeomWithData;
+
+use crate::{Id, NearestNeighbor, RoadIndex};
+
+/// A [`RoadIndex`] shared between threads, which can be replaced while it is being queried.
+///
+/// Readers take a [`RoadIndexSnapshot`] without locking, and keep seeing that version of the index
+/// for as long as they hold on to it, even if a writer swaps in a new index in the meantime.
+///
+/// # Example
+/// ```
+/// use rusty_roads::{segment_match, RoadIndex, SharedRoadIndex};
+/// use geo::wkt;
+///
+/// let shared = SharedRoadIndex::new(RoadIndex::from_ids_and_roads(
+///     &[0],
+///     &[wkt! {LINESTRING(0.0 0.0, 1.0 1.0)}],
+/// ));
+/// let snapshot = shared.snapshot();
+///
+/// // a background task publishes a new version of the index
+/// shared.replace(RoadIndex::from_ids_and_roads(
+///     &[1],
+///     &[wkt! {LINESTRING(0.0 1.0, 1.0 0.0)}],
+/// ));
+///
+/// let traj = wkt! {LINESTRING(0.1 0.1, 0.9 0.9)};
+/// let matched = segment_match(traj.lines(), &snapshot).unwrap();
+/// assert_eq!(matched.len(), 1);
+/// assert_eq!(snapshot.index.size(), 1);
+/// ```
+#[derive(Debug)]
+pub struct SharedRoadIndex {
+    current: ArcSwap<RoadIndex>,
+}
+
+impl SharedRoadIndex {
+    pub fn new(index: RoadIndex) -> SharedRoadIndex {
+        Self {
+            current: ArcSwap::from_pointee(index),
+        }
+    }
+
+    /// Returns the current version of the index.
+    pub fn snapshot(&self) -> RoadIndexSnapshot {
+        RoadIndexSnapshot(self.current.load_full())
+    }
+
+    /// Atomically replaces the index, returning the version that was replaced.
+    ///
+    /// Snapshots taken before the replacement are unaffected.
+    pub fn replace(&self, index: RoadIndex) -> RoadIndexSnapshot {
+        RoadIndexSnapshot(self.current.swap(Arc::new(index)))
+    }
+
+    /// Atomically replaces the index with a version derived from the current one, returning the replaced version.
+    ///
+    /// `update` may be called more than once if another writer replaces the index concurrently.
+    pub fn update<F>(&self, mut update: F) -> RoadIndexSnapshot
+    where
+        F: FnMut(&RoadIndex) -> RoadIndex,
+    {
+        RoadIndexSnapshot(self.current.rcu(|current| update(current)))
+    }
+}
+
+impl Default for SharedRoadIndex {
+    fn default() -> Self {
+        Self::new(RoadIndex::default())
+    }
+}
+
+impl From<RoadIndex> for SharedRoadIndex {
+    fn from(value: RoadIndex) -> Self {
+        Self::new(value)
+    }
+}
+
+/// A consistent, read-only version of a [`SharedRoadIndex`].
+///
+/// Cloning a snapshot is cheap, and it dereferences to [`RoadIndex`], so it can be passed to anything taking a `&RoadIndex`.
+#[derive(Debug, Clone)]
+pub struct RoadIndexSnapshot(Arc<RoadIndex>);
+
+impl Deref for RoadIndexSnapshot {
+    type Target = RoadIndex;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<RoadIndex> for RoadIndexSnapshot {
+    fn as_ref(&self) -> &RoadIndex {
+        &self.0
+    }
+}
+
+impl NearestNeighbor<Point, LineString<f64>> for RoadIndexSnapshot {
+    fn nearest_neighbor(&self, point: Point) -> Option<GeomWithData<LineString<f64>, Id>> {
+        self.0.nearest_neighbor(point)
+    }
+
+    fn nearest_neighbor_road(&self, point: Point, id: Id) -> Option<Point> {
+        self.0.nearest_neighbor_road(point, id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use geo::wkt;
+
+    use super::*;
+
+    fn index_with(id: Id) -> RoadIndex {
+        RoadIndex::from_ids_and_roads(&[id], &[wkt! {LINESTRING(0.0 0.0, 1.0 1.0)}])
+    }
+
+    #[test]
+    fn snapshot_survives_replace() {
+        let shared = SharedRoadIndex::new(index_with(1));
+        let before = shared.snapshot();
+        let replaced = shared.replace(index_with(2));
+
+        assert_eq!(
+            before
+                .nearest_neighbor(Point::new(0.5, 0.5))
+                .map(|g| g.data),
+            Some(1)
+        );
+        assert_eq!(
+            replaced
+                .nearest_neighbor(Point::new(0.5, 0.5))
+                .map(|g| g.data),
+            Some(1)
+        );
+        assert_eq!(
+            shared
+                .snapshot()
+                .nearest_neighbor(Point::new(0.5, 0.5))
+                .map(|g| g.data),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn update_derives_from_current() {
+        let shared = SharedRoadIndex::new(index_with(1));
+        shared.update(|current| {
+            let mut next = current.clone();
+            next.insert(2, wkt! {LINESTRING(5.0 5.0, 6.0 6.0)});
+            next
+        });
+        assert_eq!(shared.snapshot().index.size(), 2);
+    }
+
+    #[test]
+    fn concurrent_readers_see_whole_versions() {
+        let shared = SharedRoadIndex::new(index_with(0));
+        thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        let snapshot = shared.snapshot();
+                        let id = snapshot
+                            .nearest_neighbor(Point::new(0.5, 0.5))
+                            .map(|g| g.data)
+                            .expect("every version of the index contains a road");
+                        assert_eq!(snapshot.index.size(), 1);
+                        assert_eq!(
+                            snapshot.nearest_neighbor_road(Point::new(0.5, 0.5), id),
+                            Some(Point::new(0.5, 0.5))
+                        );
+                    }
+                });
+            }
+            s.spawn(|| {
+                for id in 1..100 {
+                    shared.replace(index_with(id));
+                }
+            });
+        });
+    }
+}
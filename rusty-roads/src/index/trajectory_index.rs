@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::ops::{Bound, RangeBounds};
+
+use geo::{Destination, Distance, Haversine};
+use geo_types::{Line, Point};
+use itertools::Itertools;
+use rstar::{primitives::GeomWithData, RTree, AABB};
+
+use crate::{Id, Meter, Timestamp};
+
+/// The trajectory and time span a stored segment belongs to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrajectorySegment {
+    pub trajectory_id: Id,
+    /// Time at the start of the segment
+    pub start: Timestamp,
+    /// Time at the end of the segment
+    pub end: Timestamp,
+}
+
+type IndexedSegment = GeomWithData<Line<f64>, TrajectorySegment>;
+
+/// Spatial index over the segments of stored trajectories, supporting combined spatial and temporal queries.
+///
+/// Points are assumed to be longitude/latitude, and the vehicle is assumed to move linearly between two consecutive points.
+#[derive(Debug, Clone)]
+pub struct TrajectoryIndex {
+    pub index: RTree<IndexedSegment>,
+}
+
+impl TrajectoryIndex {
+    pub fn new() -> TrajectoryIndex {
+        Self {
+            index: RTree::new(),
+        }
+    }
+
+    /// Bulk loads an index from trajectories given as their id and timestamped points.
+    pub fn from_trajectories<I, P>(trajectories: I) -> TrajectoryIndex
+    where
+        I: IntoIterator<Item = (Id, P)>,
+        P: IntoIterator<Item = (Point, Timestamp)>,
+    {
+        let segments = trajectories
+            .into_iter()
+            .flat_map(|(id, points)| segments(id, points))
+            .collect();
+        Self {
+            index: RTree::bulk_load(segments),
+        }
+    }
+
+    /// Inserts a trajectory given as timestamped points.
+    ///
+    /// A trajectory consisting of a single point is stored as a segment of length zero.
+    pub fn insert<P>(&mut self, id: Id, points: P)
+    where
+        P: IntoIterator<Item = (Point, Timestamp)>,
+    {
+        for segment in segments(id, points) {
+            self.index.insert(segment);
+        }
+    }
+
+    /// Removes every segment of the trajectory with the given id, returning the number of segments removed.
+    pub fn remove(&mut self, id: Id) -> usize {
+        let removed = self
+            .index
+            .iter()
+            .filter(|s| s.data.trajectory_id == id)
+            .cloned()
+            .collect_vec();
+        removed.iter().filter_map(|s| self.index.remove(s)).count()
+    }
+
+    /// Finds the trajectories that passed within `distance` meters of `point` during `time`.
+    ///
+    /// Returns each trajectory once, ordered by how close it got to `point`.
+    pub fn within_distance<R>(&self, point: Point, distance: Meter, time: R) -> Vec<(Id, Meter)>
+    where
+        R: RangeBounds<Timestamp>,
+    {
+        let envelope = envelope_around(point, distance);
+        let hits = self
+            .index
+            .locate_in_envelope_intersecting(&envelope)
+            .filter_map(|s| Some((s.data.trajectory_id, segment_distance(s, point, &time)?)))
+            .filter(|(_, d)| *d <= distance);
+        closest_per_trajectory(hits)
+    }
+
+    /// Finds the `k` trajectories closest to `point` during `time`, along with their distance in meters.
+    ///
+    /// # Notes
+    /// Trajectories are ranked by planar distance in degrees, which only approximates the order in meters.
+    pub fn nearest_trajectories<R>(&self, point: Point, k: usize, time: R) -> Vec<(Id, Meter)>
+    where
+        R: RangeBounds<Timestamp>,
+    {
+        if k == 0 {
+            return vec![];
+        }
+        // squared planar distance to the closest travelled point of each trajectory found so far
+        let mut closest: HashMap<Id, (f64, Point)> = HashMap::new();
+        let mut kth_closest = f64::INFINITY;
+        for (segment, bound_2) in self.index.nearest_neighbor_iter_with_distance_2(&point) {
+            // the travelled part of a segment is never closer than the whole segment
+            if bound_2 > kth_closest {
+                break;
+            }
+            let Some(travelled) = travelled(segment, &time) else {
+                continue;
+            };
+            let nearest = project(&travelled, point);
+            let distance_2 = (nearest.0 - point.0).x.powi(2) + (nearest.0 - point.0).y.powi(2);
+            let entry = closest
+                .entry(segment.data.trajectory_id)
+                .or_insert((f64::INFINITY, nearest));
+            if distance_2 < entry.0 {
+                *entry = (distance_2, nearest);
+                if closest.len() >= k {
+                    let mut distances = closest.values().map(|(d, _)| *d).collect_vec();
+                    let (_, kth, _) = distances.select_nth_unstable_by(k - 1, f64::total_cmp);
+                    kth_closest = *kth;
+                }
+            }
+        }
+        closest
+            .into_iter()
+            .sorted_by(|(_, (a, _)), (_, (b, _))| a.total_cmp(b))
+            .take(k)
+            .map(|(id, (_, nearest))| (id, Haversine.distance(nearest, point)))
+            .collect()
+    }
+}
+
+impl Default for TrajectoryIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn segments<P>(id: Id, points: P) -> Vec<IndexedSegment>
+where
+    P: IntoIterator<Item = (Point, Timestamp)>,
+{
+    let points = points.into_iter().collect_vec();
+    let segment = |(a, ta): (Point, Timestamp), (b, tb): (Point, Timestamp)| {
+        GeomWithData::new(
+            Line::new(a, b),
+            TrajectorySegment {
+                trajectory_id: id,
+                start: ta,
+                end: tb,
+            },
+        )
+    };
+    match points.as_slice() {
+        [single] => vec![segment(*single, *single)],
+        points => points
+            .iter()
+            .tuple_windows()
+            .map(|(a, b)| segment(*a, *b))
+            .collect(),
+    }
+}
+
+/// Bounding box in degrees containing every point within `distance` meters of `point`.
+fn envelope_around(point: Point, distance: Meter) -> AABB<Point> {
+    let [north, east, south, west] =
+        [0., 90., 180., 270.].map(|bearing| Haversine.destination(point, bearing, distance));
+    AABB::from_corners(
+        Point::new(west.x(), south.y()),
+        Point::new(east.x(), north.y()),
+    )
+}
+
+/// Distance in meters from `point` to the part of `segment` travelled during `time`, if any.
+fn segment_distance<R>(segment: &IndexedSegment, point: Point, time: &R) -> Option<Meter>
+where
+    R: RangeBounds<Timestamp>,
+{
+    let travelled = travelled(segment, time)?;
+    Some(Haversine.distance(project(&travelled, point), point))
+}
+
+/// The part of `segment` travelled during `time`, if any.
+fn travelled<R>(segment: &IndexedSegment, time: &R) -> Option<Line<f64>>
+where
+    R: RangeBounds<Timestamp>,
+{
+    let TrajectorySegment { start, end, .. } = segment.data;
+    let (from, after_start) = match time.start_bound() {
+        Bound::Included(t) => (*t, end >= *t),
+        Bound::Excluded(t) => (*t, end > *t),
+        Bound::Unbounded => (f64::NEG_INFINITY, true),
+    };
+    let (to, before_end) = match time.end_bound() {
+        Bound::Included(t) => (*t, start <= *t),
+        Bound::Excluded(t) => (*t, start < *t),
+        Bound::Unbounded => (f64::INFINITY, true),
+    };
+    if !after_start || !before_end {
+        return None;
+    }
+
+    let line = segment.geom();
+    Some(match end - start {
+        duration if duration > 0. => {
+            let at = |t: Timestamp| {
+                let fraction = ((t - start) / duration).clamp(0., 1.);
+                line.start + line.delta() * fraction
+            };
+            Line::new(at(from), at(to))
+        }
+        _ => *line,
+    })
+}
+
+/// Closest point on `line` to `point`, treating coordinates as planar.
+fn project(line: &Line<f64>, point: Point) -> Point {
+    let delta = line.delta();
+    let length_2 = delta.x * delta.x + delta.y * delta.y;
+    if length_2 == 0. {
+        return line.start_point();
+    }
+    let offset = point.0 - line.start;
+    let fraction = ((offset.x * delta.x + offset.y * delta.y) / length_2).clamp(0., 1.);
+    (line.start + delta * fraction).into()
+}
+
+fn closest_per_trajectory<I>(hits: I) -> Vec<(Id, Meter)>
+where
+    I: Iterator<Item = (Id, Meter)>,
+{
+    hits.into_grouping_map()
+        .min_by(|_, a, b| a.total_cmp(b))
+        .into_iter()
+        .sorted_by(|(_, a), (_, b)| a.total_cmp(b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use geo_types::point;
+
+    use super::*;
+
+    /// Three trajectories heading east through Aalborg at different times of day
+    fn index() -> TrajectoryIndex {
+        let at = |x: f64, t: Timestamp| (point! {x: x, y: 57.0}, t);
+        TrajectoryIndex::from_trajectories([
+            (1, vec![at(9.90, 0.), at(9.95, 600.), at(10.00, 1200.)]),
+            (2, vec![at(9.90, 3600.), at(9.95, 4200.), at(10.00, 4800.)]),
+            (3, vec![at(9.90, 0.)]),
+        ])
+    }
+
+    #[test]
+    fn within_distance_filters_by_time() {
+        let index = index();
+        let query = point! {x: 9.95, y: 57.0005}; // about 55 meters north of both trajectories
+
+        let morning = index.within_distance(query, 100., 0.0..=1000.);
+        assert_eq!(morning.iter().map(|(id, _)| *id).collect_vec(), vec![1]);
+        assert!((50.0..60.0).contains(&morning[0].1), "{}", morning[0].1);
+
+        let all_day = index.within_distance(query, 100., ..);
+        assert_eq!(all_day.len(), 2);
+        assert!(index.within_distance(query, 10., ..).is_empty());
+    }
+
+    #[test]
+    fn within_distance_only_considers_travelled_part() {
+        let index = index();
+        let query = point! {x: 9.99, y: 57.0};
+        // trajectory 1 only reached 9.99 after 1080 seconds
+        assert!(index.within_distance(query, 100., 0.0..=900.).is_empty());
+        assert_eq!(index.within_distance(query, 100., 0.0..=1100.).len(), 1);
+    }
+
+    #[test]
+    fn nearest_trajectories_returns_k_distinct() {
+        let index = index();
+        let query = point! {x: 9.90, y: 57.001};
+
+        let nearest = index.nearest_trajectories(query, 2, ..);
+        assert_eq!(nearest.len(), 2);
+        assert!(nearest.windows(2).all(|w| w[0].1 <= w[1].1));
+
+        let late = index.nearest_trajectories(query, 3, 3000.0..);
+        assert_eq!(late.iter().map(|(id, _)| *id).collect_vec(), vec![2]);
+    }
+
+    #[test]
+    fn nearest_trajectories_ranks_travelled_part() {
+        let mut index = TrajectoryIndex::new();
+        // passes through the query point, but only after the time window
+        index.insert(
+            1,
+            [
+                (point! {x: 9.90, y: 57.0}, 0.),
+                (point! {x: 10.10, y: 57.0}, 2000.),
+            ],
+        );
+        // about 1 km north of the query point during the time window
+        index.insert(2, [(point! {x: 10.0, y: 57.01}, 50.)]);
+        let query = point! {x: 10.0, y: 57.0};
+
+        let nearest = index.nearest_trajectories(query, 1, 0.0..=100.);
+        assert_eq!(nearest.iter().map(|(id, _)| *id).collect_vec(), vec![2]);
+        let both = index.nearest_trajectories(query, 2, 0.0..=100.);
+        assert_eq!(both.iter().map(|(id, _)| *id).collect_vec(), vec![2, 1]);
+        assert!(both[1].1 > 5000., "{}", both[1].1);
+        assert_eq!(index.nearest_trajectories(query, 1, ..)[0].0, 1);
+    }
+
+    #[test]
+    fn excluded_bounds_are_exclusive() {
+        let index = index();
+        let query = point! {x: 9.90, y: 57.0};
+        let after = (Bound::Excluded(0.), Bound::Excluded(3600.));
+        // trajectory 3 is a single point at time 0, trajectory 1 leaves it at time 0 and trajectory 2 at time 3600
+        let ids = index
+            .within_distance(query, 10., after)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect_vec();
+        assert_eq!(ids, vec![1]);
+        assert!(index.within_distance(query, 10., 3600.0..3600.).is_empty());
+        assert_eq!(index.within_distance(query, 10., 3600.0..=3600.).len(), 1);
+    }
+
+    #[test]
+    fn remove_trajectory() {
+        let mut index = index();
+        assert_eq!(index.remove(1), 2);
+        assert_eq!(index.remove(1), 0);
+        index.insert(4, [(point! {x: 9.95, y: 57.0}, 10.)]);
+        let ids = index
+            .nearest_trajectories(point! {x: 9.95, y: 57.0}, 10, ..)
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect_vec();
+        assert_eq!(ids.len(), 3);
+        assert!(!ids.contains(&1));
+    }
+}
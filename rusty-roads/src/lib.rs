@@ -1,3 +1,6 @@
+// lets code derived with `comms_macros::Table` name this crate the same way inside and outside of it
+extern crate self as rusty_roads;
+
 pub mod table;
 pub use table::*;
 
@@ -10,9 +13,23 @@ pub use graph::*;
 pub mod map_match;
 pub use map_match::*;
 
+pub mod tile;
+pub use tile::*;
+
+pub mod trajectory;
+pub use trajectory::*;
+
+pub mod evaluation;
+pub use evaluation::*;
+
+#[cfg(feature = "burn")]
+pub mod burn;
+
 #[inline]
 pub(crate) fn default<T: Default>() -> T {
     T::default()
 }
 
 pub type Id = u64;
+/// Seconds since the Unix epoch
+pub type Timestamp = f64;
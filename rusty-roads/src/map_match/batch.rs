@@ -0,0 +1,232 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use geo_types::LineString;
+use petgraph::matrix_graph::IndexType;
+use rayon::prelude::*;
+
+use crate::{Id, RoadIndex, RoadNetwork, Trajectories};
+
+use super::{hmm_match_with_config, MatchConfig, MatchError};
+
+/// How far a batch has come, as reported while it is being matched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    /// Number of trajectories matched so far, successfully or not
+    pub done: usize,
+    pub total: usize,
+    pub elapsed: Duration,
+}
+
+/// Statistics of a finished batch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatchStats {
+    pub trajectories: usize,
+    /// Number of trajectories that were matched successfully
+    pub matched: usize,
+    /// Number of trajectories that could not be matched
+    pub failed: usize,
+    /// Number of points in the trajectories of the batch
+    pub points: usize,
+    pub elapsed: Duration,
+}
+
+impl BatchStats {
+    /// Throughput in trajectories per second, or 0 if no time has elapsed
+    pub fn trajectories_per_second(&self) -> f64 {
+        self.per_second(self.trajectories)
+    }
+
+    /// Throughput in points per second, or 0 if no time has elapsed
+    pub fn points_per_second(&self) -> f64 {
+        self.per_second(self.points)
+    }
+
+    fn per_second(&self, count: usize) -> f64 {
+        match self.elapsed.is_zero() {
+            true => 0.,
+            false => count as f64 / self.elapsed.as_secs_f64(),
+        }
+    }
+}
+
+/// The result of matching every trajectory in a batch, in the order of the batch.
+#[derive(Debug)]
+pub struct BatchResult<T> {
+    pub results: Vec<(Id, Result<T, MatchError>)>,
+    pub stats: BatchStats,
+}
+
+/// Matches every trajectory in `trajectories` in parallel using `matcher`, sharing `index` and `network` between threads.
+///
+/// `matcher` is any matching entry point taking a whole trajectory and a config,
+/// e.g. [`hmm_match_with_config`] or [`hmm_match_points_with_config`](super::hmm_match_points_with_config).
+/// `progress` is called from the worker threads every time a trajectory has been matched.
+///
+/// Only ids and geometries with a counterpart are matched, if `trajectories` has more of one than of the other.
+pub fn match_batch<Idx, T, F, P>(
+    trajectories: &Trajectories,
+    index: &RoadIndex,
+    network: &RoadNetwork<Idx>,
+    config: &MatchConfig,
+    matcher: F,
+    progress: P,
+) -> BatchResult<T>
+where
+    Idx: IndexType + Send + Sync,
+    T: Send,
+    F: Fn(&LineString<f64>, &RoadIndex, &RoadNetwork<Idx>, &MatchConfig) -> Result<T, MatchError>
+        + Sync,
+    P: Fn(Progress) + Sync,
+{
+    let start = Instant::now();
+    let total = trajectories.id.len().min(trajectories.geom.len());
+    let done = AtomicUsize::new(0);
+
+    let results: Vec<_> = trajectories
+        .id
+        .par_iter()
+        .zip(trajectories.geom.par_iter())
+        .map(|(id, trajectory)| {
+            let result = matcher(trajectory, index, network, config);
+            progress(Progress {
+                done: done.fetch_add(1, Ordering::Relaxed) + 1,
+                total,
+                elapsed: start.elapsed(),
+            });
+            (*id, result)
+        })
+        .collect();
+
+    let matched = results.iter().filter(|(_, r)| r.is_ok()).count();
+    let stats = BatchStats {
+        trajectories: total,
+        matched,
+        failed: total - matched,
+        points: trajectories.geom[..total].iter().map(|g| g.0.len()).sum(),
+        elapsed: start.elapsed(),
+    };
+    BatchResult { results, stats }
+}
+
+/// Matches every trajectory in `trajectories` in parallel with [`hmm_match_with_config`], without reporting progress.
+pub fn hmm_match_batch<Idx>(
+    trajectories: &Trajectories,
+    index: &RoadIndex,
+    network: &RoadNetwork<Idx>,
+    config: &MatchConfig,
+) -> BatchResult<Vec<Id>>
+where
+    Idx: IndexType + Send + Sync,
+{
+    match_batch(
+        trajectories,
+        index,
+        network,
+        config,
+        hmm_match_with_config,
+        |_| {},
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use geo::wkt;
+
+    use super::super::{fixtures, hmm_match_points_with_config};
+    use super::*;
+
+    fn trajectories() -> Trajectories {
+        Trajectories {
+            id: vec![7, 8, 9],
+            geom: vec![
+                wkt! {LINESTRING(10.0005 57.00005, 10.0055 57.00005)},
+                wkt! {LINESTRING(10.0005 57.00005, 11.0 58.0)},
+                wkt! {LINESTRING(10.0045 57.00005, 10.0055 57.00005, 10.0059 57.00005)},
+            ],
+        }
+    }
+
+    #[test]
+    fn matches_in_order() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+
+        let batch = hmm_match_batch(&trajectories(), &index, &network, &MatchConfig::default());
+        assert_eq!(
+            batch.results,
+            vec![
+                (7, Ok(vec![0, 1, 2])),
+                (8, Err(MatchError::NoCandidates(1))),
+                (9, Ok(vec![2])),
+            ]
+        );
+        assert_eq!((batch.stats.matched, batch.stats.failed), (2, 1));
+        assert_eq!(batch.stats.points, 7);
+    }
+
+    #[test]
+    fn reports_progress() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+
+        let reported = Mutex::new(vec![]);
+        let batch = match_batch(
+            &trajectories(),
+            &index,
+            &network,
+            &MatchConfig::default(),
+            hmm_match_points_with_config,
+            |p| reported.lock().expect("lock is not poisoned").push(p),
+        );
+        let mut reported = reported.into_inner().expect("lock is not poisoned");
+        reported.sort_by_key(|p| p.done);
+        assert_eq!(
+            reported.iter().map(|p| p.done).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert!(reported
+            .iter()
+            .all(|p| p.total == 3 && p.elapsed <= batch.stats.elapsed));
+        assert!(batch.stats.points_per_second() > 0.);
+    }
+
+    #[test]
+    fn counts_only_trajectories_with_an_id() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+
+        let mut trajectories = trajectories();
+        trajectories.id.pop();
+        let batch = hmm_match_batch(&trajectories, &index, &network, &MatchConfig::default());
+        assert_eq!(batch.results.len(), 2);
+        assert_eq!(batch.stats.trajectories, 2);
+        assert_eq!(batch.stats.points, 4);
+    }
+
+    #[test]
+    fn empty_batch_has_no_throughput() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+
+        let batch = hmm_match_batch(
+            &Trajectories::default(),
+            &index,
+            &network,
+            &MatchConfig::default(),
+        );
+        assert!(batch.results.is_empty());
+        let stats = BatchStats {
+            elapsed: Duration::ZERO,
+            ..batch.stats
+        };
+        assert_eq!(stats.trajectories_per_second(), 0.);
+        assert_eq!(stats.points_per_second(), 0.);
+    }
+}
@@ -0,0 +1,91 @@
+use geo::{closest_point::ClosestPoint, Closest, LineLocatePoint};
+use geo_types::{LineString, Point};
+use rstar::primitives::GeomWithData;
+
+use crate::{Id, Meter, RoadIndex};
+
+use super::config::{DistanceMetric, MatchConfig};
+use super::GapReason;
+
+/// A road that an observed point may have been on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Candidate {
+    pub road: Id,
+    /// The observed point projected onto the road
+    pub point: Point,
+    /// Fraction of the road's length from its start to [`Candidate::point`]
+    pub fraction: f64,
+    /// Distance in meters from the observed point to [`Candidate::point`]
+    pub distance: Meter,
+}
+
+impl Candidate {
+    /// Projects `point` onto `road`, failing if the closest point on the road is indeterminate.
+    pub fn project(
+        road: Id,
+        geom: &LineString<f64>,
+        point: Point,
+        metric: DistanceMetric,
+    ) -> Option<Candidate> {
+        let projected = match geom.closest_point(&point) {
+            Closest::SinglePoint(p) | Closest::Intersection(p) => p,
+            Closest::Indeterminate => return None,
+        };
+        Some(Candidate {
+            road,
+            point: projected,
+            fraction: geom.line_locate_point(&projected).unwrap_or(0.),
+            distance: metric.distance(projected, point),
+        })
+    }
+}
+
+/// Finds up to `config.max_candidates` roads within `config.max_radius` meters of `point`, closest first.
+pub(crate) fn candidates(index: &RoadIndex, point: Point, config: &MatchConfig) -> Vec<Candidate> {
+    find_candidates(index, point, config).unwrap_or_default()
+}
+
+/// Like [`candidates`], but tells why there are none.
+pub(crate) fn find_candidates(
+    index: &RoadIndex,
+    point: Point,
+    config: &MatchConfig,
+) -> Result<Vec<Candidate>, GapReason> {
+    let mut indeterminate = false;
+    let mut candidates: Vec<_> = index
+        .index
+        .nearest_neighbor_iter(&point)
+        .take(config.max_candidates)
+        .filter_map(|road| {
+            let candidate = Candidate::project(road.data, road.geom(), point, config.metric);
+            indeterminate |= candidate.is_none();
+            candidate
+        })
+        .filter(|c| c.distance <= config.max_radius)
+        .collect();
+    if candidates.is_empty() {
+        return Err(match indeterminate {
+            true => GapReason::Indeterminate,
+            false => GapReason::NoCandidates,
+        });
+    }
+    candidates.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+    Ok(candidates)
+}
+
+/// Like [`candidates`], but returns the roads themselves, in the order of the index.
+pub(crate) fn nearby_roads<'i>(
+    index: &'i RoadIndex,
+    point: Point,
+    config: &MatchConfig,
+) -> Vec<&'i GeomWithData<LineString<f64>, Id>> {
+    index
+        .index
+        .nearest_neighbor_iter(&point)
+        .take(config.max_candidates)
+        .filter(|road| {
+            Candidate::project(road.data, road.geom(), point, config.metric)
+                .is_some_and(|c| c.distance <= config.max_radius)
+        })
+        .collect()
+}
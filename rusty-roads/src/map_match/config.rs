@@ -0,0 +1,331 @@
+use geo::{Distance, Euclidean, Geodesic, Haversine};
+use geo_types::{LineString, Point};
+use thiserror::Error;
+
+use crate::{Id, Meter};
+
+use super::{HmmParams, LayerParams, ScoringWeights};
+
+/// How distances from observed points to candidate roads are measured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DistanceMetric {
+    /// Great circle distance on a sphere, which is fast and accurate to about 0.5%
+    #[default]
+    Haversine,
+    /// Distance on the WGS84 ellipsoid, which is accurate but slower
+    Geodesic,
+    /// Planar distance, for trajectories and roads in a projected coordinate system measured in meters
+    Euclidean,
+}
+
+impl DistanceMetric {
+    pub fn distance(&self, a: Point, b: Point) -> Meter {
+        match self {
+            DistanceMetric::Haversine => Haversine.distance(a, b),
+            DistanceMetric::Geodesic => Geodesic.distance(a, b),
+            DistanceMetric::Euclidean => Euclidean.distance(a, b),
+        }
+    }
+}
+
+/// Configuration shared by the map matching entry points taking a config, such as [`hmm_match_with_config`](super::hmm_match_with_config).
+///
+/// The defaults are valid, see [`MatchConfig::validate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchConfig {
+    /// Maximum number of candidate roads considered per point
+    pub max_candidates: usize,
+    /// Maximum distance in meters from a point to its candidate roads
+    pub max_radius: Meter,
+    /// How the distance from a point to a candidate road is measured
+    pub metric: DistanceMetric,
+    /// Weights used to score candidates by [`segment_match_scored_with_config`](super::segment_match_scored_with_config),
+    /// including [`ScoringWeights::segment_layer_penalty`], which the hidden Markov model does not read
+    pub weights: ScoringWeights,
+    /// Parameters of the hidden Markov model used by [`hmm_match_with_config`](super::hmm_match_with_config)
+    pub hmm: HmmParams,
+    /// How [`hmm_match_with_config`](super::hmm_match_with_config) uses the layers, bridges and tunnels of roads,
+    /// including [`LayerParams::hmm_layer_penalty`], which segment matching does not read
+    pub layers: LayerParams,
+    /// How many times faster than the highest speed limit on a route a timestamped trajectory may drive it,
+    /// before [`hmm_match_timed`](super::hmm_match_timed) considers the route implausible
+    pub speed_tolerance: f64,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        Self {
+            max_candidates: 10,
+            max_radius: 200.0,
+            metric: DistanceMetric::default(),
+            weights: ScoringWeights::default(),
+            hmm: HmmParams::default(),
+            layers: LayerParams::default(),
+            speed_tolerance: 1.5,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum MatchConfigError {
+    #[error("at least one candidate must be considered per point")]
+    NoCandidates,
+    #[error("the search radius must be positive, but was {0}")]
+    Radius(Meter),
+    #[error("the {0} weight must be finite and non-negative, but was {1}")]
+    Weight(&'static str, f64),
+    #[error("the hidden Markov model parameter {0} must be finite and positive, but was {1}")]
+    HmmParam(&'static str, f64),
+    #[error("the speed tolerance must be positive, but was {0}")]
+    SpeedTolerance(f64),
+    #[error("the tunnel gap must be positive, but was {0}")]
+    TunnelGap(f64),
+}
+
+impl MatchConfig {
+    /// Checks that the configuration can be used for matching.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no candidates are considered, if the radius is not positive,
+    /// if a weight is negative or not finite, or if a parameter of the hidden Markov model, the speed tolerance or the tunnel gap is not positive.
+    pub fn validate(&self) -> Result<(), MatchConfigError> {
+        if self.max_candidates == 0 {
+            return Err(MatchConfigError::NoCandidates);
+        }
+        if self.max_radius.is_nan() || self.max_radius <= 0. {
+            return Err(MatchConfigError::Radius(self.max_radius));
+        }
+        let weights = [
+            ("distance", self.weights.distance),
+            ("heading", self.weights.heading),
+            ("wrong way", self.weights.wrong_way),
+            ("same point", self.weights.same_point),
+            ("segment layer penalty", self.weights.segment_layer_penalty),
+            (
+                "hidden Markov model layer penalty",
+                self.layers.hmm_layer_penalty,
+            ),
+            ("tunnel bonus", self.layers.tunnel_bonus),
+        ];
+        if let Some((name, w)) = weights
+            .into_iter()
+            .find(|(_, w)| !(w.is_finite() && *w >= 0.))
+        {
+            return Err(MatchConfigError::Weight(name, w));
+        }
+        let params = [("sigma", self.hmm.sigma), ("beta", self.hmm.beta)];
+        if let Some((name, p)) = params
+            .into_iter()
+            .find(|(_, p)| !(p.is_finite() && *p > 0.))
+        {
+            return Err(MatchConfigError::HmmParam(name, p));
+        }
+        if self.speed_tolerance.is_nan() || self.speed_tolerance <= 0. {
+            return Err(MatchConfigError::SpeedTolerance(self.speed_tolerance));
+        }
+        if self.layers.tunnel_gap.is_nan() || self.layers.tunnel_gap <= 0. {
+            return Err(MatchConfigError::TunnelGap(self.layers.tunnel_gap));
+        }
+        Ok(())
+    }
+}
+
+/// How well a configuration matched the ground truth in [`sweep`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct SweepResult {
+    pub config: MatchConfig,
+    /// Mean accuracy over all trajectories, between 0 and 1
+    pub accuracy: f64,
+    /// Number of trajectories that could not be matched, which count as 0 accuracy
+    pub failed: usize,
+}
+
+/// Evaluates every configuration in `configs` by matching each trajectory in `ground_truth` with `matcher`,
+/// and comparing the result with the roads the trajectory is known to have driven.
+///
+/// The accuracy of a single trajectory is the length of the longest common subsequence of the matched and the true road ids,
+/// divided by the length of the longer of the two, so both missed and extra roads are penalized.
+///
+/// Invalid configurations are skipped. The results are sorted best first.
+///
+/// # Example
+/// ```
+/// use itertools::iproduct;
+/// use rusty_roads::{segment_match_detailed_with_config, road_ids, sweep, MatchConfig, RoadIndex};
+/// use geo::wkt;
+///
+/// let index = RoadIndex::from_ids_and_roads(
+///     &[7, 8],
+///     &[wkt!{LINESTRING(0.0 2.0, 2.0 3.0)}, wkt!{LINESTRING(2.0 3.0, 4.0 4.0)}],
+/// );
+/// let ground_truth = [(wkt!{LINESTRING(1.0 2.5, 2.0 3.0, 3.0 3.5)}, vec![7, 8])];
+///
+/// let configs = iproduct!([1, 5], [10.0, 100.0]).map(|(max_candidates, max_radius)| MatchConfig {
+///     max_candidates,
+///     max_radius,
+///     ..Default::default()
+/// });
+/// let results = sweep(configs, &ground_truth, |traj, config| {
+///     segment_match_detailed_with_config(traj.lines(), &index, config)
+///         .map(|matched| road_ids(matched.iter().flat_map(|s| [&s.start, &s.end])))
+/// });
+/// assert_eq!(results.len(), 4);
+/// assert_eq!(results[0].accuracy, 1.0);
+/// ```
+pub fn sweep<C, M, E>(
+    configs: C,
+    ground_truth: &[(LineString<f64>, Vec<Id>)],
+    mut matcher: M,
+) -> Vec<SweepResult>
+where
+    C: IntoIterator<Item = MatchConfig>,
+    M: FnMut(&LineString<f64>, &MatchConfig) -> Result<Vec<Id>, E>,
+{
+    let mut results: Vec<_> = configs
+        .into_iter()
+        .filter(|config| config.validate().is_ok())
+        .map(|config| {
+            let mut failed = 0;
+            let total: f64 = ground_truth
+                .iter()
+                .map(|(trajectory, truth)| match matcher(trajectory, &config) {
+                    Ok(matched) => accuracy(&matched, truth),
+                    Err(_) => {
+                        failed += 1;
+                        0.
+                    }
+                })
+                .sum();
+            SweepResult {
+                config,
+                accuracy: total / ground_truth.len().max(1) as f64,
+                failed,
+            }
+        })
+        .collect();
+    results.sort_by(|fst, snd| snd.accuracy.total_cmp(&fst.accuracy));
+    results
+}
+
+/// Length of the longest common subsequence of `matched` and `truth`, relative to the longer of the two.
+fn accuracy(matched: &[Id], truth: &[Id]) -> f64 {
+    let longest = matched.len().max(truth.len());
+    if longest == 0 {
+        return 1.;
+    }
+    let mut row = vec![0usize; truth.len() + 1];
+    for m in matched {
+        let mut diagonal = 0;
+        for (j, t) in truth.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = match m == t {
+                true => diagonal + 1,
+                false => above.max(row[j]),
+            };
+            diagonal = above;
+        }
+    }
+    row[truth.len()] as f64 / longest as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::wkt;
+
+    use super::super::{
+        fixtures, hmm_match_with_config, segment_match_detailed_with_config, segment_match_partial,
+        MatchError,
+    };
+    use super::*;
+
+    #[test]
+    fn default_is_valid() {
+        assert_eq!(MatchConfig::default().validate(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        let config = MatchConfig {
+            max_candidates: 0,
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(MatchConfigError::NoCandidates));
+
+        let config = MatchConfig {
+            max_radius: f64::NAN,
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(MatchConfigError::Radius(_))
+        ));
+
+        let mut config = MatchConfig::default();
+        config.weights.heading = -1.;
+        assert_eq!(
+            config.validate(),
+            Err(MatchConfigError::Weight("heading", -1.))
+        );
+
+        let mut config = MatchConfig::default();
+        config.hmm.beta = 0.;
+        assert_eq!(
+            config.validate(),
+            Err(MatchConfigError::HmmParam("beta", 0.))
+        );
+    }
+
+    #[test]
+    fn entry_points_reject_invalid() {
+        let roads = fixtures::roads();
+        let index = fixtures::index(&roads);
+        let traj = wkt! {LINESTRING(10.0005 57.00005, 10.0055 57.00005)};
+        let config = MatchConfig {
+            max_candidates: 0,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            segment_match_detailed_with_config(traj.lines(), &index, &config),
+            Err(MatchError::InvalidConfig(MatchConfigError::NoCandidates))
+        );
+        assert!(matches!(
+            segment_match_partial(traj.lines(), &index, &config),
+            Err(MatchError::InvalidConfig(MatchConfigError::NoCandidates))
+        ));
+    }
+
+    #[test]
+    fn accuracy_penalizes_missing_and_extra_roads() {
+        assert_eq!(accuracy(&[1, 2, 3], &[1, 2, 3]), 1.);
+        assert_eq!(accuracy(&[1, 3], &[1, 2, 3, 4]), 0.5);
+        assert_eq!(accuracy(&[1, 5, 2, 3], &[1, 2, 3]), 0.75);
+        assert_eq!(accuracy(&[], &[]), 1.);
+    }
+
+    #[test]
+    fn sweep_ranks_configs() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+
+        let ground_truth = [(
+            wkt! {LINESTRING(10.0005 57.00005, 10.0055 57.00005)},
+            vec![0, 1, 2],
+        )];
+        let configs = [1., 200., -1.].map(|max_radius| MatchConfig {
+            max_radius,
+            ..Default::default()
+        });
+        let results = sweep(configs, &ground_truth, |traj, config| {
+            hmm_match_with_config(traj, &index, &network, config)
+        });
+
+        assert_eq!(results.len(), 2, "the negative radius should be skipped");
+        assert_eq!(results[0].config.max_radius, 200.);
+        assert_eq!(results[0].accuracy, 1.);
+        assert_eq!(results[1].failed, 1);
+        assert_eq!(results[1].accuracy, 0.);
+    }
+}
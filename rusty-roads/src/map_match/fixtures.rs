@@ -0,0 +1,51 @@
+//! A small road network near Aalborg shared by the map matching tests.
+
+use geo::wkt;
+
+use crate::{Direction, NodeId, Road, RoadIndex, RoadNetwork, RoadWithNode};
+
+pub(crate) fn road(id: u64, geom: geo_types::LineString<f64>) -> Road {
+    Road {
+        id,
+        geom,
+        osm_id: id,
+        code: 5113,
+        direction: Direction::Bidirectional,
+        maxspeed: 50,
+        layer: 0,
+        bridge: false,
+        tunnel: false,
+    }
+}
+
+/// Three connected roads heading east along latitude 57, each about 120 meters long,
+/// and a disconnected road running parallel about 45 meters north of them.
+pub(crate) fn roads() -> Vec<(Road, NodeId, NodeId)> {
+    vec![
+        (road(0, wkt! {LINESTRING(10.000 57.0, 10.002 57.0)}), 1, 2),
+        (road(1, wkt! {LINESTRING(10.002 57.0, 10.004 57.0)}), 2, 3),
+        (road(2, wkt! {LINESTRING(10.004 57.0, 10.006 57.0)}), 3, 4),
+        (
+            road(3, wkt! {LINESTRING(10.000 57.0004, 10.006 57.0004)}),
+            10,
+            11,
+        ),
+    ]
+}
+
+pub(crate) fn network(roads: &[(Road, NodeId, NodeId)]) -> RoadNetwork<'_, u16> {
+    RoadNetwork::new(roads.iter().map(|(road, source, target)| RoadWithNode {
+        road,
+        source: *source,
+        target: *target,
+    }))
+    .expect("test network should be valid")
+}
+
+pub(crate) fn index(roads: &[(Road, NodeId, NodeId)]) -> RoadIndex {
+    let (ids, geoms): (Vec<_>, Vec<_>) = roads
+        .iter()
+        .map(|(road, _, _)| (road.id, road.geom.clone()))
+        .unzip();
+    RoadIndex::from_ids_and_roads(&ids, &geoms)
+}
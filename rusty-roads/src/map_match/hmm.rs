@@ -0,0 +1,666 @@
+use std::f64::consts::PI;
+use std::ops::Range;
+
+use geo::{Distance, Haversine};
+use geo_types::{Line, LineString, Point};
+use itertools::Itertools;
+use petgraph::matrix_graph::IndexType;
+use thiserror::Error;
+
+use crate::{Id, Meter, RoadIndex, RoadNetwork, TimedTrajectory, Timestamp};
+
+use super::candidate::{candidates, find_candidates, Candidate};
+use super::layers::layer_transition;
+use super::router::{Route, Router};
+use super::{GapReason, MatchConfig, MatchConfigError, MatchedPoint, PartialMatch};
+
+/// Parameters of the hidden Markov model used by [`hmm_match`].
+///
+/// The defaults are the values found by Newson & Krumm (2009).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HmmParams {
+    /// Standard deviation of GPS noise in meters
+    pub sigma: Meter,
+    /// Scale in meters of the difference between route distance and great circle distance
+    pub beta: Meter,
+}
+
+impl Default for HmmParams {
+    fn default() -> Self {
+        Self {
+            sigma: 4.07,
+            beta: 3.0,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum MatchError {
+    #[error("cannot match an empty trajectory")]
+    EmptyTrajectory,
+    #[error("no candidate roads within the search radius of point {0}")]
+    NoCandidates(usize),
+    #[error("no route between the candidates of point {0} and point {1}")]
+    Disconnected(usize, usize),
+    #[error("every route between the candidates of point {0} and point {1} is too long to drive in the time between them")]
+    Implausible(usize, usize),
+    #[error("road {0} is not part of the road network")]
+    UnknownRoad(Id),
+    #[error("segment {0} could not be matched")]
+    UnmatchedSegment(usize, Line),
+    #[error("invalid match config: {0}")]
+    InvalidConfig(#[from] MatchConfigError),
+}
+
+/// Matches a trajectory to the road network using a hidden Markov model, in the style of Newson & Krumm (2009).
+///
+/// Candidate roads are found near each point using `index`, which must use the same road ids as `network`.
+/// A candidate is more likely the closer it is to the point, and a transition between candidates is more likely
+/// the closer the driving distance between them ([`RoadNetwork::path_find`]) is to the great circle distance between the points.
+///
+/// Returns the sequence of roads driven, including the roads driven between points, where every road is connected to the next.
+///
+/// Up to 10 candidate roads within 200 meters of each point are considered, see [`hmm_match_with_config`] to change them.
+///
+/// # Errors
+///
+/// This function will return an error if `params` are invalid, if the trajectory is empty, if a point has no candidate roads,
+/// or if no candidate of a point can be reached from any candidate of the previous point.
+pub fn hmm_match<Idx: IndexType>(
+    trajectory: &LineString<f64>,
+    index: &RoadIndex,
+    network: &RoadNetwork<Idx>,
+    params: &HmmParams,
+) -> Result<Vec<Id>, MatchError> {
+    hmm_match_with_config(trajectory, index, network, &hmm_config(params))
+}
+
+/// Like [`hmm_match`], but with the candidates, distance metric and layer handling given by `config`.
+///
+/// # Errors
+///
+/// This function will return an error if `config` is invalid, or for the same reasons as [`hmm_match`].
+pub fn hmm_match_with_config<Idx: IndexType>(
+    trajectory: &LineString<f64>,
+    index: &RoadIndex,
+    network: &RoadNetwork<Idx>,
+    config: &MatchConfig,
+) -> Result<Vec<Id>, MatchError> {
+    let decoded = viterbi(&Observation::untimed(trajectory), index, network, config)?;
+    Ok(roads_driven(decoded))
+}
+
+/// Like [`hmm_match`], but returns the road and position every point of the trajectory was matched to.
+///
+/// # Errors
+///
+/// This function will return an error if `params` are invalid, if the trajectory is empty, if a point has no candidate roads,
+/// or if no candidate of a point can be reached from any candidate of the previous point.
+pub fn hmm_match_points<Idx: IndexType>(
+    trajectory: &LineString<f64>,
+    index: &RoadIndex,
+    network: &RoadNetwork<Idx>,
+    params: &HmmParams,
+) -> Result<Vec<MatchedPoint>, MatchError> {
+    hmm_match_points_with_config(trajectory, index, network, &hmm_config(params))
+}
+
+/// Like [`hmm_match_points`], but with the candidates, distance metric and layer handling given by `config`.
+///
+/// # Errors
+///
+/// This function will return an error if `config` is invalid, or for the same reasons as [`hmm_match_points`].
+pub fn hmm_match_points_with_config<Idx: IndexType>(
+    trajectory: &LineString<f64>,
+    index: &RoadIndex,
+    network: &RoadNetwork<Idx>,
+    config: &MatchConfig,
+) -> Result<Vec<MatchedPoint>, MatchError> {
+    let decoded = viterbi(&Observation::untimed(trajectory), index, network, config)?;
+    Ok(decoded.into_iter().map(|(matched, _)| matched).collect())
+}
+
+/// The configuration [`hmm_match`] and [`hmm_match_points`] match with, which are the defaults apart from `params`
+fn hmm_config(params: &HmmParams) -> MatchConfig {
+    MatchConfig {
+        hmm: *params,
+        ..Default::default()
+    }
+}
+
+/// Like [`hmm_match`], but also rules out routes between two points that cannot be driven in the time between them.
+///
+/// A route is too long if driving it would take going more than `config.speed_tolerance` times the highest speed limit
+/// ([`Road::maxspeed`](crate::Road::maxspeed), in km/h) of its roads, allowing for the reported accuracy of the points.
+/// Routes on roads without a speed limit are never ruled out.
+///
+/// # Errors
+///
+/// This function will return an error for the same reasons as [`hmm_match_with_config`],
+/// or if every route from the candidates of a point to the candidates of the next point is too long.
+pub fn hmm_match_timed<Idx: IndexType>(
+    trajectory: &TimedTrajectory,
+    index: &RoadIndex,
+    network: &RoadNetwork<Idx>,
+    config: &MatchConfig,
+) -> Result<Vec<Id>, MatchError> {
+    let decoded = viterbi(&Observation::timed(trajectory), index, network, config)?;
+    Ok(roads_driven(decoded))
+}
+
+/// Like [`hmm_match_timed`], but returns the road and position every point of the trajectory was matched to.
+///
+/// # Errors
+///
+/// This function will return an error for the same reasons as [`hmm_match_timed`].
+pub fn hmm_match_points_timed<Idx: IndexType>(
+    trajectory: &TimedTrajectory,
+    index: &RoadIndex,
+    network: &RoadNetwork<Idx>,
+    config: &MatchConfig,
+) -> Result<Vec<MatchedPoint>, MatchError> {
+    let decoded = viterbi(&Observation::timed(trajectory), index, network, config)?;
+    Ok(decoded.into_iter().map(|(matched, _)| matched).collect())
+}
+
+/// Like [`hmm_match_points`], but instead of failing when part of the trajectory cannot be matched,
+/// matches as much of the trajectory as possible and reports the points that could not be matched and why.
+///
+/// Points without candidate roads are left out, and the trajectory is split where no route connects two points.
+///
+/// # Errors
+///
+/// This function will return an error if `config` is invalid.
+pub fn hmm_match_partial<Idx: IndexType>(
+    trajectory: &LineString<f64>,
+    index: &RoadIndex,
+    network: &RoadNetwork<Idx>,
+    config: &MatchConfig,
+) -> Result<PartialMatch<MatchedPoint>, MatchError> {
+    decode_partial(&Observation::untimed(trajectory), index, network, config)
+}
+
+/// Like [`hmm_match_partial`], but also splits the trajectory where every route between two points
+/// is too long to drive in the time between them, as in [`hmm_match_timed`].
+///
+/// # Errors
+///
+/// This function will return an error if `config` is invalid.
+pub fn hmm_match_partial_timed<Idx: IndexType>(
+    trajectory: &TimedTrajectory,
+    index: &RoadIndex,
+    network: &RoadNetwork<Idx>,
+    config: &MatchConfig,
+) -> Result<PartialMatch<MatchedPoint>, MatchError> {
+    decode_partial(&Observation::timed(trajectory), index, network, config)
+}
+
+fn decode_partial<Idx: IndexType>(
+    observations: &[Observation],
+    index: &RoadIndex,
+    network: &RoadNetwork<Idx>,
+    config: &MatchConfig,
+) -> Result<PartialMatch<MatchedPoint>, MatchError> {
+    config.validate()?;
+    let mut partial = PartialMatch::default();
+    let mut run_start = None;
+    for (i, observation) in observations.iter().enumerate() {
+        match find_candidates(index, observation.point, config) {
+            Ok(_) => {
+                run_start.get_or_insert(i);
+            }
+            Err(reason) => {
+                if let Some(start) = run_start.take() {
+                    decode_run(observations, start..i, index, network, config, &mut partial)?;
+                }
+                partial.push_unmatched(i, reason);
+            }
+        }
+    }
+    if let Some(start) = run_start {
+        let run = start..observations.len();
+        decode_run(observations, run, index, network, config, &mut partial)?;
+    }
+    Ok(partial)
+}
+
+/// Decodes a run of points which all have candidates, splitting it wherever two points cannot be connected.
+fn decode_run<Idx: IndexType>(
+    observations: &[Observation],
+    run: Range<usize>,
+    index: &RoadIndex,
+    network: &RoadNetwork<Idx>,
+    config: &MatchConfig,
+    partial: &mut PartialMatch<MatchedPoint>,
+) -> Result<(), MatchError> {
+    let mut start = run.start;
+    while start < run.end {
+        let (end, reason) = match viterbi(&observations[start..run.end], index, network, config) {
+            Ok(decoded) => {
+                push_decoded(partial, start, decoded);
+                return Ok(());
+            }
+            Err(MatchError::Disconnected(_, next)) => (start + next, GapReason::Disconnected),
+            Err(MatchError::Implausible(_, next)) => (start + next, GapReason::Implausible),
+            Err(error) => return Err(error),
+        };
+        let decoded = viterbi(&observations[start..end], index, network, config)?;
+        push_decoded(partial, start, decoded);
+        partial.push_break(end, reason);
+        start = end;
+    }
+    Ok(())
+}
+
+fn push_decoded(
+    partial: &mut PartialMatch<MatchedPoint>,
+    start: usize,
+    decoded: Vec<(MatchedPoint, Vec<Id>)>,
+) {
+    let matched = decoded.iter().map(|(matched, _)| *matched).collect();
+    partial.push_matched(start, matched, roads_driven(decoded));
+}
+
+/// The roads driven through the decoded candidates, without repeating a road.
+fn roads_driven(decoded: Vec<(MatchedPoint, Vec<Id>)>) -> Vec<Id> {
+    let mut roads = vec![];
+    for (matched, route) in decoded {
+        roads.extend(route);
+        roads.push(matched.road);
+    }
+    roads.dedup();
+    roads
+}
+
+/// A point to be matched, with the time it was observed at if it is known.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Observation {
+    pub point: Point,
+    pub time: Option<Timestamp>,
+    /// Accuracy in meters of the point, if it was reported
+    pub accuracy: Option<Meter>,
+}
+
+impl Observation {
+    pub fn untimed(trajectory: &LineString<f64>) -> Vec<Observation> {
+        trajectory
+            .points()
+            .map(|point| Observation {
+                point,
+                time: None,
+                accuracy: None,
+            })
+            .collect()
+    }
+
+    pub fn timed(trajectory: &TimedTrajectory) -> Vec<Observation> {
+        trajectory
+            .into_iter()
+            .map(|p| Observation {
+                point: p.point,
+                time: Some(p.time),
+                accuracy: p.accuracy,
+            })
+            .collect()
+    }
+}
+
+/// Index of the best candidate at the previous point, and the roads driven from it, if it can be reached at all
+pub(crate) type BackPointer = Option<(usize, Vec<Id>)>;
+
+/// Finds the most likely candidate for every point, along with the roads driven to get there from the previous candidate.
+pub(crate) fn viterbi<Idx: IndexType>(
+    observations: &[Observation],
+    index: &RoadIndex,
+    network: &RoadNetwork<Idx>,
+    config: &MatchConfig,
+) -> Result<Vec<(MatchedPoint, Vec<Id>)>, MatchError> {
+    config.validate()?;
+    let params = &config.hmm;
+    let layers: Vec<Vec<Candidate>> = observations
+        .iter()
+        .enumerate()
+        .map(|(i, o)| match candidates(index, o.point, config) {
+            c if c.is_empty() => Err(MatchError::NoCandidates(i)),
+            c => Ok(c),
+        })
+        .try_collect()?;
+    let first = layers.first().ok_or(MatchError::EmptyTrajectory)?;
+
+    let mut router = Router::new(network);
+    let mut scores = first.iter().map(|c| emission(c, params)).collect_vec();
+    // for every point after the first, the best predecessor of each candidate and the roads driven from it
+    let mut back: Vec<Vec<BackPointer>> = Vec::with_capacity(observations.len());
+
+    for (t, (prev, layer)) in layers.iter().tuple_windows().enumerate() {
+        let (a, b) = (&observations[t], &observations[t + 1]);
+        let Step {
+            scores: next_scores,
+            back: pointers,
+            pruned,
+        } = step(&mut router, (a, prev, &scores), (b, layer), config);
+
+        if next_scores.iter().all(|s| !s.is_finite()) {
+            return Err(match pruned {
+                true => MatchError::Implausible(t, t + 1),
+                false => MatchError::Disconnected(t, t + 1),
+            });
+        }
+        scores = next_scores;
+        back.push(pointers);
+    }
+
+    let mut best = scores
+        .iter()
+        .position_max_by(|fst, snd| fst.total_cmp(snd))
+        .ok_or(MatchError::EmptyTrajectory)?;
+    let mut decoded = Vec::with_capacity(observations.len());
+    for (layer, pointers) in layers.iter().skip(1).zip(back.iter()).rev() {
+        let (prev, roads) = pointers[best]
+            .clone()
+            .expect("a candidate with a finite score should have a predecessor");
+        decoded.push((MatchedPoint::from_candidate(layer[best], Some(best)), roads));
+        best = prev;
+    }
+    decoded.push((
+        MatchedPoint::from_candidate(first[best], Some(best)),
+        vec![],
+    ));
+    decoded.reverse();
+    Ok(decoded)
+}
+
+/// The result of moving the Viterbi algorithm one point forward.
+pub(crate) struct Step {
+    /// Log probability of the most likely sequence of candidates ending in each candidate of the new point
+    pub scores: Vec<f64>,
+    pub back: Vec<BackPointer>,
+    /// Whether a route was ruled out by [`plausible`]
+    pub pruned: bool,
+}
+
+/// Moves from the candidates of `prev`, which have the given scores, to the candidates of `next`.
+pub(crate) fn step<Idx: IndexType>(
+    router: &mut Router<'_, '_, Idx>,
+    (a, prev, scores): (&Observation, &[Candidate], &[f64]),
+    (b, layer): (&Observation, &[Candidate]),
+    config: &MatchConfig,
+) -> Step {
+    let params = &config.hmm;
+    let great_circle = Haversine.distance(a.point, b.point);
+    let mut pruned = false;
+    let (scores, back) = layer
+        .iter()
+        .map(|candidate| {
+            prev.iter()
+                .zip(scores.iter())
+                .enumerate()
+                .filter(|(_, (_, score))| score.is_finite())
+                .filter_map(|(i, (from, score))| {
+                    let route = router.route(from, candidate)?;
+                    if !plausible(
+                        &route,
+                        router.max_speed(from, candidate, &route),
+                        a,
+                        b,
+                        config,
+                    ) {
+                        pruned = true;
+                        return None;
+                    }
+                    let score = score
+                        + transition(route.distance, great_circle, params)
+                        + layer_score(router, from, candidate, &route, (a, b), config);
+                    Some((score, (i, route.roads)))
+                })
+                .max_by(|(fst, _), (snd, _)| fst.total_cmp(snd))
+                .map_or((f64::NEG_INFINITY, None), |(score, pointer)| {
+                    (score + emission(candidate, params), Some(pointer))
+                })
+        })
+        .unzip();
+    Step {
+        scores,
+        back,
+        pruned,
+    }
+}
+
+/// Log probability adjustment for the layers, bridges and tunnels of the roads driven from `from` to `to`, see [`LayerParams`](super::LayerParams).
+fn layer_score<Idx: IndexType>(
+    router: &Router<'_, '_, Idx>,
+    from: &Candidate,
+    to: &Candidate,
+    route: &Route,
+    (a, b): (&Observation, &Observation),
+    config: &MatchConfig,
+) -> f64 {
+    let (Some(from_road), Some(to_road)) = (router.road(from.road), router.road(to.road)) else {
+        return 0.;
+    };
+    let through = route.roads.iter().filter_map(|id| router.road(*id));
+    let gap = a.time.zip(b.time).map(|(start, end)| end - start);
+    layer_transition(from_road, to_road, through, gap, &config.layers)
+}
+
+/// Whether `route` from `a` to `b` can be driven in the time between them without going faster than `max_speed` tolerates.
+fn plausible(
+    route: &Route,
+    max_speed: Option<f64>,
+    a: &Observation,
+    b: &Observation,
+    config: &MatchConfig,
+) -> bool {
+    let (Some(start), Some(end), Some(max_speed)) = (a.time, b.time, max_speed) else {
+        return true;
+    };
+    let noise = a.accuracy.unwrap_or(config.hmm.sigma) + b.accuracy.unwrap_or(config.hmm.sigma);
+    route.distance <= max_speed * config.speed_tolerance * (end - start) + noise
+}
+
+/// Log probability of observing a point `candidate.distance` meters from the road it was on.
+pub(crate) fn emission(candidate: &Candidate, params: &HmmParams) -> f64 {
+    let z = candidate.distance / params.sigma;
+    -0.5 * z * z - (params.sigma * (2. * PI).sqrt()).ln()
+}
+
+/// Log probability of driving `route` meters between two points `great_circle` meters apart.
+pub(crate) fn transition(route: Meter, great_circle: Meter, params: &HmmParams) -> f64 {
+    -(route - great_circle).abs() / params.beta - params.beta.ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::wkt;
+
+    use super::super::fixtures;
+    use super::*;
+
+    #[test]
+    fn stays_on_connected_roads() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+
+        // the third point is closer to the disconnected parallel road
+        let trajectory = wkt! {LINESTRING(
+            10.0005 57.00005,
+            10.0025 56.99995,
+            10.0035 57.00025,
+            10.0055 57.00005
+        )};
+        let matched = hmm_match(&trajectory, &index, &network, &HmmParams::default());
+        assert_eq!(matched, Ok(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn reports_matched_points() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+
+        let trajectory = wkt! {LINESTRING(10.0005 57.00005, 10.0035 57.00025)};
+        let matched = hmm_match_points(&trajectory, &index, &network, &HmmParams::default())
+            .expect("trajectory should match");
+        assert_eq!(matched.len(), 2);
+        assert_eq!(matched.iter().map(|m| m.road).collect_vec(), vec![0, 1]);
+        assert_eq!(
+            matched[1].rank,
+            Some(1),
+            "the parallel road is closer to the second point"
+        );
+        assert!((matched[0].fraction - 0.25).abs() < 1e-6);
+        assert!(
+            (matched[0].distance - 5.6).abs() < 0.1,
+            "{}",
+            matched[0].distance
+        );
+        assert_eq!(matched[0].point.y(), 57.0);
+    }
+
+    #[test]
+    fn fills_in_skipped_roads() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+
+        let trajectory = wkt! {LINESTRING(10.0005 57.00005, 10.0055 57.00005)};
+        let matched = hmm_match(&trajectory, &index, &network, &HmmParams::default());
+        assert_eq!(matched, Ok(vec![0, 1, 2]));
+    }
+
+    #[test]
+    fn rules_out_speeding() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+        let trajectory = wkt! {LINESTRING(10.0005 57.00005, 10.0055 57.00005)};
+
+        // about 300 meters in 30 seconds is 36 km/h
+        let timed =
+            TimedTrajectory::from_times(&trajectory, &[0., 30.]).expect("times are ordered");
+        let matched = hmm_match_timed(&timed, &index, &network, &MatchConfig::default());
+        assert_eq!(matched, Ok(vec![0, 1, 2]));
+
+        // about 300 meters in 5 seconds is 218 km/h on 50 km/h roads
+        let timed = TimedTrajectory::from_times(&trajectory, &[0., 5.]).expect("times are ordered");
+        let matched = hmm_match_timed(&timed, &index, &network, &MatchConfig::default());
+        assert_eq!(matched, Err(MatchError::Implausible(0, 1)));
+    }
+
+    #[test]
+    fn matches_around_gaps() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+
+        let trajectory = wkt! {LINESTRING(
+            10.0005 57.00005,
+            10.0015 57.00005,
+            11.0 58.0,
+            11.0 58.1,
+            10.0045 57.00005,
+            10.0055 57.00005
+        )};
+        let partial = hmm_match_partial(&trajectory, &index, &network, &MatchConfig::default())
+            .expect("config is valid");
+        assert!(!partial.is_complete());
+        assert_eq!(
+            partial
+                .matched
+                .iter()
+                .map(|m| (m.span(), m.roads.clone()))
+                .collect_vec(),
+            vec![(0..2, vec![0]), (4..6, vec![2])]
+        );
+        assert_eq!(partial.unmatched.len(), 1);
+        assert_eq!(partial.unmatched[0].span, 2..4);
+        assert_eq!(partial.unmatched[0].reason, GapReason::NoCandidates);
+
+        // jumps from the chain to the disconnected road and stays on it
+        let trajectory = wkt! {LINESTRING(
+            10.0005 56.9999,
+            10.0015 56.9999,
+            10.0055 57.0005,
+            10.0045 57.0005
+        )};
+        let config = MatchConfig {
+            max_radius: 20.,
+            ..Default::default()
+        };
+        let partial =
+            hmm_match_partial(&trajectory, &index, &network, &config).expect("config is valid");
+        assert_eq!(
+            partial
+                .matched
+                .iter()
+                .map(|m| (m.span(), m.roads.clone()))
+                .collect_vec(),
+            vec![(0..2, vec![0]), (2..4, vec![3])]
+        );
+        assert_eq!(partial.unmatched[0].span, 2..2);
+        assert_eq!(partial.unmatched[0].reason, GapReason::Disconnected);
+        assert_eq!(partial.matched_count(), 4);
+    }
+
+    #[test]
+    fn stays_below_bridge() {
+        let mut bridge = fixtures::road(2, wkt! {LINESTRING(10.002 57.0, 10.004 57.00003)});
+        (bridge.layer, bridge.bridge) = (1, true);
+        let roads = vec![
+            (
+                fixtures::road(0, wkt! {LINESTRING(10.000 57.0, 10.002 57.0)}),
+                1,
+                2,
+            ),
+            (
+                fixtures::road(1, wkt! {LINESTRING(10.002 57.0, 10.004 57.0)}),
+                2,
+                3,
+            ),
+            (bridge, 2, 4),
+        ];
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+
+        // the second point is closer to the bridge rising above the street
+        let trajectory = wkt! {LINESTRING(10.0005 57.00001, 10.0035 57.00002)};
+        let matched = hmm_match(&trajectory, &index, &network, &HmmParams::default());
+        assert_eq!(matched, Ok(vec![0, 1]));
+
+        let mut config = MatchConfig::default();
+        config.layers.hmm_layer_penalty = 0.;
+        let matched = hmm_match_with_config(&trajectory, &index, &network, &config);
+        assert_eq!(matched, Ok(vec![0, 2]));
+    }
+
+    #[test]
+    fn no_candidates() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+
+        let trajectory = wkt! {LINESTRING(10.0005 57.00005, 11.0 58.0)};
+        let matched = hmm_match(&trajectory, &index, &network, &HmmParams::default());
+        assert_eq!(matched, Err(MatchError::NoCandidates(1)));
+        let empty = LineString::new(vec![]);
+        let matched = hmm_match(&empty, &index, &network, &HmmParams::default());
+        assert_eq!(matched, Err(MatchError::EmptyTrajectory));
+    }
+
+    #[test]
+    fn disconnected() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+
+        // jumps from the chain to the far end of the disconnected road
+        let trajectory = wkt! {LINESTRING(10.0005 56.9999, 10.0055 57.0005)};
+        let config = MatchConfig {
+            max_radius: 20.,
+            ..Default::default()
+        };
+        let matched = hmm_match_with_config(&trajectory, &index, &network, &config);
+        assert_eq!(matched, Err(MatchError::Disconnected(0, 1)));
+    }
+}
@@ -0,0 +1,109 @@
+use crate::{Id, Queryable, Road, RoadKey, Roads};
+
+/// How [`hmm_match`](super::hmm_match) uses the layer, bridge and tunnel attributes of roads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LayerParams {
+    /// Subtracted from the log probability of a transition for every level between the roads of its candidates,
+    /// so a trajectory is kept on the street below a bridge rather than jumping between them,
+    /// which only applies to [`hmm_match`](super::hmm_match)
+    pub hmm_layer_penalty: f64,
+    /// Seconds without a point after which the signal is considered lost
+    pub tunnel_gap: f64,
+    /// Added to the log probability of a transition through a tunnel when the signal was lost between its points
+    pub tunnel_bonus: f64,
+}
+
+impl Default for LayerParams {
+    fn default() -> Self {
+        Self {
+            hmm_layer_penalty: 2.0,
+            tunnel_gap: 10.0,
+            tunnel_bonus: 2.0,
+        }
+    }
+}
+
+/// The vertical level of a road, where bridges are above and tunnels are below ground even if their layer is not set.
+pub(crate) fn level(layer: i16, bridge: bool, tunnel: bool) -> i16 {
+    match (bridge, tunnel) {
+        (true, _) if layer <= 0 => 1,
+        (_, true) if layer >= 0 => -1,
+        _ => layer,
+    }
+}
+
+pub(crate) fn road_level(road: &Road) -> i16 {
+    level(road.layer, road.bridge, road.tunnel)
+}
+
+/// The level of the road with the given id, or 0 if it is not in `roads`.
+pub(crate) fn level_of(roads: &Roads, id: Id) -> i16 {
+    roads.find_index(&RoadKey(id)).map_or(0, |i| {
+        level(roads.layer[i], roads.bridge[i], roads.tunnel[i])
+    })
+}
+
+/// Adjusts the log probability of driving from `from` over the roads `through` to `to`,
+/// where `gap` is the number of seconds between the points, if known.
+pub(crate) fn layer_transition<'r, I>(
+    from: &Road,
+    to: &Road,
+    mut through: I,
+    gap: Option<f64>,
+    params: &LayerParams,
+) -> f64
+where
+    I: Iterator<Item = &'r Road>,
+{
+    let change = (road_level(from) - road_level(to)).unsigned_abs();
+    let lost_signal = gap.is_some_and(|gap| gap >= params.tunnel_gap);
+    let tunnel = from.tunnel || to.tunnel || through.any(|r| r.tunnel);
+    let bonus = match lost_signal && tunnel {
+        true => params.tunnel_bonus,
+        false => 0.,
+    };
+    bonus - params.hmm_layer_penalty * f64::from(change)
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::wkt;
+
+    use super::super::fixtures;
+    use super::*;
+
+    #[test]
+    fn bridges_and_tunnels_have_levels() {
+        assert_eq!(level(0, true, false), 1);
+        assert_eq!(level(2, true, false), 2);
+        assert_eq!(level(0, false, true), -1);
+        assert_eq!(level(-2, false, true), -2);
+        assert_eq!(level(0, false, false), 0);
+    }
+
+    #[test]
+    fn rewards_tunnels_after_signal_loss() {
+        let params = LayerParams::default();
+        let street = fixtures::road(0, wkt! {LINESTRING(10.000 57.0, 10.001 57.0)});
+        let mut tunnel = fixtures::road(1, wkt! {LINESTRING(10.001 57.0, 10.002 57.0)});
+        tunnel.tunnel = true;
+
+        let through = || [&tunnel].into_iter();
+        assert_eq!(
+            layer_transition(&street, &street, through(), None, &params),
+            0.
+        );
+        assert_eq!(
+            layer_transition(&street, &street, through(), Some(30.), &params),
+            params.tunnel_bonus
+        );
+        assert_eq!(
+            layer_transition(&street, &street, std::iter::empty(), Some(30.), &params),
+            0.
+        );
+        assert_eq!(
+            layer_transition(&street, &tunnel, std::iter::empty(), Some(1.), &params),
+            -params.hmm_layer_penalty
+        );
+    }
+}
@@ -1,17 +1,22 @@
+use crate::Id;
 use crate::RoadIndex;
 use crate::Roads;
 
+use super::candidate::{find_candidates, nearby_roads, Candidate};
+use super::layers::level_of;
+use super::scoring::{direction_of, score_candidate, ScoringWeights};
+use super::{
+    road_ids, DistanceMetric, GapReason, MatchConfig, MatchError, MatchedPoint, MatchedSegment,
+    PartialMatch,
+};
+
 use super::super::Road;
 use super::super::RoadWithNode;
 use geo::closest_point::ClosestPoint;
 use geo::Closest;
-use geo::Distance;
 use geo::Euclidean;
-use geo::Geodesic;
-use geo::Length;
 use geo::Point;
 use geo::{Line, LineString, MultiLineString};
-use itertools::Itertools;
 use rstar::primitives::GeomWithData;
 
 type Trajectory = LineString<f64>;
@@ -37,22 +42,21 @@ impl ClosestPoint<f64> for RoadWithNode<'_> {
 
 /// Compares direction of 2 lines
 /// returns a number between 0 and 2 (inclusive) where 0 means their direction is identical and 2 means they are opposite (sqrt(2) meaning a perfect right angle)
-fn line_similarity(fst: &Line, snd: &Line) -> f64 {
-    let fst = Line::new(
-        fst.start / Geodesic.length(fst),
-        fst.end / Geodesic.length(fst),
-    );
-    let snd = Line::new(
-        snd.start / Geodesic.length(snd),
-        snd.end / Geodesic.length(snd),
-    );
-    let res = Line::new(fst.start - snd.start, fst.end - snd.end);
-    let length = match Geodesic.length(&res) {
-        l if l.is_normal() => l,
-        _ => 0.,
+///
+/// Longitudes are scaled by the cosine of the latitude, so directions are compared as they appear on the ground.
+pub(super) fn line_similarity(fst: &Line, snd: &Line) -> f64 {
+    let direction = |l: &Line| {
+        let scale = ((l.start.y + l.end.y) / 2.).to_radians().cos();
+        let (dx, dy) = (l.dx() * scale, l.dy());
+        let length = dx.hypot(dy);
+        (dx / length, dy / length)
     };
-    debug_assert!((0.0..=2.0).contains(&length), "{length}");
-    length
+    let (fst_x, fst_y) = direction(fst);
+    let (snd_x, snd_y) = direction(snd);
+    match (fst_x - snd_x).hypot(fst_y - snd_y) {
+        l if l.is_normal() => l.min(2.),
+        _ => 0.,
+    }
 }
 
 /// attempts to match an input trajectory to the given road network
@@ -85,49 +89,344 @@ pub fn segment_match<I>(sub_traj: I, index: &RoadIndex) -> Result<Vec<Line>, (us
 where
     I: Iterator<Item = Line>,
 {
-    const MAX_CANDIDATES: usize = 20/2; // max k in index knn query // completely arbitrary
+    let matched = segment_match_detailed(sub_traj, index)?;
+    Ok(matched.into_iter().map(Line::from).collect())
+}
+
+/// Like [`segment_match`], but with the candidates, distance metric and weights given by `config`.
+///
+/// # Panics
+///
+/// Panics if the rtree is empty .
+///
+/// # Errors
+///
+/// This function will return an error if `config` is invalid, or if any point in the trajectory cannot be matched
+/// (i.e. closest point is indeterminate, or no road is within `config.max_radius`).
+pub fn segment_match_with_config<I>(
+    sub_traj: I,
+    index: &RoadIndex,
+    config: &MatchConfig,
+) -> Result<Vec<Line>, MatchError>
+where
+    I: Iterator<Item = Line>,
+{
+    let matched = segment_match_detailed_with_config(sub_traj, index, config)?;
+    Ok(matched.into_iter().map(Line::from).collect())
+}
+
+/// Like [`segment_match`], but reports which road each segment was matched to, and where on the road its endpoints were matched.
+///
+/// # Panics
+///
+/// Panics if the rtree is empty .
+///
+/// # Errors
+///
+/// This function will return an error if any point in the trajectory cannot be matched (i.e. closest point is indeterminate).
+///
+/// # Example
+/// ```
+/// use rusty_roads::{road_ids, segment_match_detailed, RoadIndex};
+/// use geo::wkt;
+///
+/// let traj = wkt!{LINESTRING(1.0 2.1, 2.0 3.1, 3.0 4.1)};
+/// let rtree = RoadIndex::from_ids_and_roads(
+///     &[7, 8],
+///     &[wkt!{LINESTRING(0.0 2.0, 2.0 3.0)}, wkt!{LINESTRING(2.0 3.0, 4.0 4.0)}],
+/// );
+/// let matched = segment_match_detailed(traj.lines(), &rtree).unwrap();
+/// assert_eq!(matched[0].start.road, 7);
+/// assert_eq!(road_ids(matched.iter().flat_map(|s| [&s.start, &s.end])), vec![7, 8]);
+/// ```
+pub fn segment_match_detailed<I>(
+    sub_traj: I,
+    index: &RoadIndex,
+) -> Result<Vec<MatchedSegment>, (usize, Line)>
+where
+    I: Iterator<Item = Line>,
+{
+    let config = unlimited_config(DistanceMetric::Geodesic);
+    match_segments(sub_traj, index, &config, distance_score(&config))
+}
+
+/// Like [`segment_match_detailed`], but with the candidates, distance metric and weights given by `config`.
+///
+/// # Panics
+///
+/// Panics if the rtree is empty .
+///
+/// # Errors
+///
+/// This function will return an error if `config` is invalid, or if any point in the trajectory cannot be matched
+/// (i.e. closest point is indeterminate, or no road is within `config.max_radius`).
+///
+/// # Example
+/// ```
+/// use rusty_roads::{segment_match_detailed_with_config, MatchConfig, MatchError, RoadIndex};
+/// use geo::wkt;
+///
+/// let rtree = RoadIndex::from_ids_and_roads(&[7], &[wkt!{LINESTRING(10.0 57.0, 10.002 57.0)}]);
+/// let traj = wkt!{LINESTRING(10.0005 57.00005, 10.0015 57.00005)};
+/// let matched = segment_match_detailed_with_config(traj.lines(), &rtree, &MatchConfig::default()).unwrap();
+/// assert_eq!(matched[0].start.road, 7);
+///
+/// // the last segment is about a kilometer from the road, which is more than the default radius of 200 meters
+/// let traj = wkt!{LINESTRING(10.0005 57.00005, 10.0015 57.01, 10.0005 57.01)};
+/// let unmatched = segment_match_detailed_with_config(traj.lines(), &rtree, &MatchConfig::default());
+/// assert!(matches!(unmatched, Err(MatchError::UnmatchedSegment(1, _))));
+/// ```
+pub fn segment_match_detailed_with_config<I>(
+    sub_traj: I,
+    index: &RoadIndex,
+    config: &MatchConfig,
+) -> Result<Vec<MatchedSegment>, MatchError>
+where
+    I: Iterator<Item = Line>,
+{
+    config.validate()?;
+    match_segments(sub_traj, index, config, distance_score(config))
+        .map_err(|(idx, l)| MatchError::UnmatchedSegment(idx, l))
+}
 
+/// The configuration [`segment_match`], [`segment_match_detailed`] and [`segment_match_scored`] match with,
+/// which measures distances with `metric` and considers candidate roads at any distance
+fn unlimited_config(metric: DistanceMetric) -> MatchConfig {
+    MatchConfig {
+        max_radius: f64::INFINITY,
+        metric,
+        ..Default::default()
+    }
+}
+
+/// Scores a candidate road by the distance from the endpoints of a segment to it.
+fn distance_score(
+    config: &MatchConfig,
+) -> impl Fn(&Line, &GeomWithData<LineString<f64>, Id>, Option<Id>) -> Option<f64> + '_ {
+    |l, g, _| {
+        let (closest_start, _) = closest(&l.start_point(), g.geom()).ok()?;
+        let (closest_end, _) = closest(&l.end_point(), g.geom()).ok()?; // Note: if every candidate causes a None value here, the matched trajectory will have smaller cardinality
+
+        let f_dist = config.metric.distance(closest_start, l.start_point());
+        let l_dist = config.metric.distance(closest_end, l.end_point());
+        let w = match closest_start == closest_end {
+            false => 1.0,
+            true => config.weights.same_point,
+        };
+
+        Some((f_dist + l_dist) * w)
+    }
+}
+
+/// Like [`segment_match_detailed`], but scores candidate roads by distance, by how well their direction agrees with the segment,
+/// by whether the segment drives a one-way road the wrong way, and by how many levels the road is above or below
+/// the road matched to the previous segment, as weighted by `weights`.
+/// The last term keeps a trajectory on the street below a bridge, or on the bridge above it, rather than jumping between them.
+///
+/// `roads` provides the direction and level of the roads in `index`.
+/// Roads missing from `roads` are treated as bidirectional and on the ground.
+///
+/// # Panics
+///
+/// Panics if the rtree is empty .
+///
+/// # Errors
+///
+/// This function will return an error if any point in the trajectory cannot be matched (i.e. closest point is indeterminate).
+pub fn segment_match_scored<I>(
+    sub_traj: I,
+    index: &RoadIndex,
+    roads: &Roads,
+    weights: &ScoringWeights,
+) -> Result<Vec<MatchedSegment>, (usize, Line)>
+where
+    I: Iterator<Item = Line>,
+{
+    let config = MatchConfig {
+        weights: *weights,
+        ..unlimited_config(DistanceMetric::Haversine)
+    };
+    match_segments(sub_traj, index, &config, scored(roads, &config))
+}
+
+/// Like [`segment_match_scored`], but with the candidates, distance metric and weights given by `config`.
+///
+/// # Panics
+///
+/// Panics if the rtree is empty .
+///
+/// # Errors
+///
+/// This function will return an error if `config` is invalid, or if any point in the trajectory cannot be matched
+/// (i.e. closest point is indeterminate, or no road is within `config.max_radius`).
+pub fn segment_match_scored_with_config<I>(
+    sub_traj: I,
+    index: &RoadIndex,
+    roads: &Roads,
+    config: &MatchConfig,
+) -> Result<Vec<MatchedSegment>, MatchError>
+where
+    I: Iterator<Item = Line>,
+{
+    config.validate()?;
+    match_segments(sub_traj, index, config, scored(roads, config))
+        .map_err(|(idx, l)| MatchError::UnmatchedSegment(idx, l))
+}
+
+/// Scores a candidate road as described in [`segment_match_scored`].
+fn scored<'a>(
+    roads: &'a Roads,
+    config: &'a MatchConfig,
+) -> impl Fn(&Line, &GeomWithData<LineString<f64>, Id>, Option<Id>) -> Option<f64> + 'a {
+    |l, road, prev| {
+        let score = score_candidate(
+            l,
+            road.geom(),
+            direction_of(roads, road.data),
+            &config.weights,
+            config.metric,
+        )?;
+        let change = prev.map_or(0, |prev| {
+            (level_of(roads, road.data) - level_of(roads, prev)).unsigned_abs()
+        });
+        Some(score + config.weights.segment_layer_penalty * f64::from(change))
+    }
+}
+
+/// Like [`segment_match_detailed`], but instead of failing on the first segment that cannot be matched,
+/// matches as much of the trajectory as possible and reports the segments that could not be matched and why.
+///
+/// Candidates are scored by distance only, as in [`segment_match_detailed`], so unlike [`segment_match_scored`]
+/// it does not keep to the layer of the road matched to the previous segment.
+///
+/// Segment indices count from the first segment of `sub_traj`.
+///
+/// # Panics
+///
+/// Panics if the rtree is empty .
+///
+/// # Errors
+///
+/// This function will return an error if `config` is invalid.
+///
+/// # Example
+/// ```
+/// use rusty_roads::{segment_match_partial, GapReason, MatchConfig, RoadIndex};
+/// use geo::wkt;
+///
+/// let rtree = RoadIndex::from_ids_and_roads(
+///     &[7, 8],
+///     &[wkt!{LINESTRING(10.0 57.0, 10.001 57.0)}, wkt!{LINESTRING(10.002 57.0, 10.003 57.0)}],
+/// );
+/// // the third segment lies entirely more than 200 meters from any road
+/// let traj = wkt!{LINESTRING(10.0 57.0, 10.001 57.0, 10.0012 57.01, 10.0018 57.01, 10.002 57.0, 10.003 57.0)};
+/// let matched = segment_match_partial(traj.lines(), &rtree, &MatchConfig::default()).unwrap();
+/// assert_eq!(matched.matched.len(), 2);
+/// assert_eq!(matched.matched[0].span(), 0..2);
+/// assert_eq!(matched.matched[1].roads, vec![8]);
+/// assert_eq!(matched.unmatched[0].span, 2..3);
+/// assert_eq!(matched.unmatched[0].reason, GapReason::NoCandidates);
+/// ```
+pub fn segment_match_partial<I>(
+    sub_traj: I,
+    index: &RoadIndex,
+    config: &MatchConfig,
+) -> Result<PartialMatch<MatchedSegment>, MatchError>
+where
+    I: Iterator<Item = Line>,
+{
     debug_assert!(index.index.size() >= 1, "rtree index should be nonempty");
+    config.validate()?;
+
+    let mut partial = PartialMatch::default();
+    let mut run: Vec<MatchedSegment> = vec![];
+    let mut run_start = 0;
+    let score = distance_score(config);
+    for (idx, l) in sub_traj.enumerate() {
+        match match_segment(&l, index, config, &score, None) {
+            Ok(segment) => {
+                if run.is_empty() {
+                    run_start = idx;
+                }
+                run.push(segment);
+            }
+            Err(reason) => {
+                let matched = std::mem::take(&mut run);
+                let roads = road_ids(matched.iter().flat_map(|s| [&s.start, &s.end]));
+                partial.push_matched(run_start, matched, roads);
+                partial.push_unmatched(idx, reason);
+            }
+        }
+    }
+    let roads = road_ids(run.iter().flat_map(|s| [&s.start, &s.end]));
+    partial.push_matched(run_start, run, roads);
+    Ok(partial)
+}
 
-    let matched = sub_traj.enumerate().map(|(idx, l)| {
-        let candidate_roads_start = index
-            .index
-            .nearest_neighbor_iter_with_distance_2(&l.start_point())
-            .take(MAX_CANDIDATES);
-        let candidate_roads_end = index
-            .index
-            .nearest_neighbor_iter_with_distance_2(&l.end_point())
-            .take(MAX_CANDIDATES);
-
-        // gather candidate roads from start and end roads
-        let all_candidates = candidate_roads_start.chain(candidate_roads_end);
-
-        // find the road with with smallest distance to a line segment
-        let (best, _dist) = all_candidates
-            .filter_map(|(g, _)| {
-                let (closest_start, _) = closest(&l.start_point(), g.geom()).ok()?;
-                let (closest_end, _) = closest(&l.end_point(), g.geom()).ok()?; // Note: if every candidate causes a None value here, the matched trajectory will have smaller cardinality
-
-                let f_dist = Geodesic.distance(closest_start, l.start_point());
-                let l_dist = Geodesic.distance(closest_end, l.end_point());
-                let w = match closest_start == closest_end {
-                    false => 1.0,
-                    true => 2.0, // also completely arbitrary
-                };
-
-                Some((g, (f_dist + l_dist) * w))
-            })
-            .min_by(|(_, fst), (_, snd)| fst.total_cmp(snd))
-            .ok_or((idx, l))?; // unlikely, but can be triggered if all nn's have indeterminate closest point
-        let start_matched = closest(&l.start_point(), best.geom()).map_err(|_| (idx, l))?;
-        let end_matched = closest(&l.end_point(), best.geom()).map_err(|_| (idx, l))?;
-
-        Ok((start_matched, end_matched))
-    });
-
-    let result: Result<Vec<_>, (usize, Line)> =
-        matched.map_ok(|(a, b)| Line::new(a.0, b.0)).try_collect();
-    result
+/// Matches every segment to the candidate road with the lowest score, where `score` returns [`None`] for roads that cannot be matched.
+///
+/// `score` is also given the road matched to the previous segment, if any.
+fn match_segments<I, S>(
+    sub_traj: I,
+    index: &RoadIndex,
+    config: &MatchConfig,
+    score: S,
+) -> Result<Vec<MatchedSegment>, (usize, Line)>
+where
+    I: Iterator<Item = Line>,
+    S: Fn(&Line, &GeomWithData<LineString<f64>, Id>, Option<Id>) -> Option<f64>,
+{
+    debug_assert!(index.index.size() >= 1, "rtree index should be nonempty");
+
+    let mut matched: Vec<MatchedSegment> = vec![];
+    for (idx, l) in sub_traj.enumerate() {
+        let prev = matched.last().map(|s| s.end.road);
+        let segment = match_segment(&l, index, config, &score, prev).map_err(|_| (idx, l))?;
+        matched.push(segment);
+    }
+    Ok(matched)
+}
+
+/// Matches a single segment to the candidate road with the lowest score.
+fn match_segment<S>(
+    l: &Line,
+    index: &RoadIndex,
+    config: &MatchConfig,
+    score: &S,
+    prev: Option<Id>,
+) -> Result<MatchedSegment, GapReason>
+where
+    S: Fn(&Line, &GeomWithData<LineString<f64>, Id>, Option<Id>) -> Option<f64>,
+{
+    let candidate_roads_start = nearby_roads(index, l.start_point(), config);
+    let candidate_roads_end = nearby_roads(index, l.end_point(), config);
+    if candidate_roads_start.is_empty() && candidate_roads_end.is_empty() {
+        return Err(find_candidates(index, l.start_point(), config)
+            .err()
+            .unwrap_or(GapReason::NoCandidates));
+    }
+
+    // gather candidate roads from start and end roads
+    let all_candidates = candidate_roads_start
+        .iter()
+        .chain(candidate_roads_end.iter());
+
+    // find the road with with smallest score
+    let (best, _score) = all_candidates
+        .filter_map(|g| Some((g, score(l, g, prev)?)))
+        .min_by(|(_, fst), (_, snd)| fst.total_cmp(snd))
+        .ok_or(GapReason::Indeterminate)?; // unlikely, but can be triggered if all nn's have indeterminate closest point
+
+    let matched_point = |p: Point, candidates: &[&GeomWithData<LineString<f64>, Id>]| {
+        let rank = candidates.iter().position(|g| g.data == best.data);
+        Candidate::project(best.data, best.geom(), p, config.metric)
+            .map(|c| MatchedPoint::from_candidate(c, rank))
+            .ok_or(GapReason::Indeterminate)
+    };
+    Ok(MatchedSegment {
+        start: matched_point(l.start_point(), &candidate_roads_start)?,
+        end: matched_point(l.end_point(), &candidate_roads_end)?,
+    })
 }
 
 fn closest(p: &Point, first_nn: &LineString) -> Result<(Point, Point), Point> {
@@ -142,7 +441,7 @@ fn closest(p: &Point, first_nn: &LineString) -> Result<(Point, Point), Point> {
 mod tests {
 
     use geo::line_measures::FrechetDistance;
-    use geo::{coord, wkt, Closest, Coord, Euclidean, Point};
+    use geo::{coord, wkt, Closest, Coord, Euclidean, Geodesic, Length, Point};
     use geo_traits::MultiLineStringTrait;
     use geo_types::line_string;
 
@@ -263,6 +562,20 @@ mod tests {
         assert!((line_sim- f64::sqrt(2.0)).abs() < 0.001,"\tLeft = {}\n\tRight = {}",line_sim,f64::sqrt(2.0));
     }
 
+    #[test]
+    fn slope_away_from_equator() {
+        let east = Line::new(coord! {x: 9.90, y: 57.0}, coord! {x: 9.91, y: 57.0});
+        let west = Line::new(coord! {x: 9.91, y: 57.0}, coord! {x: 9.90, y: 57.0});
+        // about 600 meters east and 600 meters north at latitude 57
+        let north_east = Line::new(coord! {x: 9.90, y: 57.0}, coord! {x: 9.91, y: 57.0054});
+
+        assert!((line_similarity(&east, &west) - 2.0).abs() < 0.001);
+        assert!(line_similarity(&east, &east) < 0.001);
+        let diagonal = line_similarity(&east, &north_east);
+        let expected = 2. * (std::f64::consts::PI / 8.).sin();
+        assert!((diagonal - expected).abs() < 0.01, "{diagonal}");
+    }
+
     #[test]
     #[ignore = "just playing with things"]
     fn lines_vs_points() {
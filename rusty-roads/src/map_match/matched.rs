@@ -0,0 +1,71 @@
+use geo_types::{Line, Point};
+
+use crate::{Id, Meter};
+
+use super::candidate::Candidate;
+
+/// A trajectory point matched to a road.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchedPoint {
+    /// Id of the road the point was matched to
+    pub road: Id,
+    /// The observed point projected onto the road
+    pub point: Point,
+    /// Fraction of the road's length from its start to [`MatchedPoint::point`]
+    pub fraction: f64,
+    /// Distance in meters from the observed point to [`MatchedPoint::point`]
+    pub distance: Meter,
+    /// Position of the road among the candidate roads of the point ordered by distance, where 0 is the closest,
+    /// or [`None`] if the road is not a candidate of the point, e.g. when a segment is matched to a candidate of its other endpoint
+    pub rank: Option<usize>,
+}
+
+impl MatchedPoint {
+    pub(crate) fn from_candidate(candidate: Candidate, rank: Option<usize>) -> MatchedPoint {
+        let Candidate {
+            road,
+            point,
+            fraction,
+            distance,
+        } = candidate;
+        Self {
+            road,
+            point,
+            fraction,
+            distance,
+            rank,
+        }
+    }
+
+    pub(crate) fn candidate(&self) -> Candidate {
+        Candidate {
+            road: self.road,
+            point: self.point,
+            fraction: self.fraction,
+            distance: self.distance,
+        }
+    }
+}
+
+/// The start and end of a trajectory segment matched to the same road.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MatchedSegment {
+    pub start: MatchedPoint,
+    pub end: MatchedPoint,
+}
+
+impl From<MatchedSegment> for Line {
+    fn from(value: MatchedSegment) -> Self {
+        Line::new(value.start.point, value.end.point)
+    }
+}
+
+/// The roads visited by a sequence of matched points, without consecutive repetitions.
+pub fn road_ids<'a, I>(matched: I) -> Vec<Id>
+where
+    I: IntoIterator<Item = &'a MatchedPoint>,
+{
+    let mut roads: Vec<Id> = matched.into_iter().map(|m| m.road).collect();
+    roads.dedup();
+    roads
+}
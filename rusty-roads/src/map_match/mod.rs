@@ -1,2 +1,25 @@
 mod map_match;
-pub use map_match::*;
\ No newline at end of file
+pub use map_match::*;
+mod candidate;
+mod config;
+pub use config::*;
+mod matched;
+pub use matched::*;
+mod partial;
+pub use partial::*;
+mod router;
+mod scoring;
+pub use scoring::ScoringWeights;
+mod layers;
+pub use layers::LayerParams;
+mod hmm;
+pub use hmm::*;
+mod online;
+pub use online::*;
+mod reconstruct;
+pub use reconstruct::*;
+mod batch;
+pub use batch::*;
+
+#[cfg(test)]
+pub(crate) mod fixtures;
@@ -0,0 +1,354 @@
+use std::collections::VecDeque;
+
+use geo_types::Point;
+use itertools::Itertools;
+use petgraph::matrix_graph::IndexType;
+
+use crate::{Id, RoadIndex, RoadNetwork, TimedPoint};
+
+use super::candidate::{candidates, Candidate};
+use super::hmm::{emission, step, BackPointer, Observation, Step};
+use super::router::Router;
+use super::{MatchConfig, MatchConfigError, MatchError, MatchedPoint};
+
+/// Number of shortest paths remembered between points before they are forgotten, to keep memory bounded on long trips.
+const MAX_CACHED_PATHS: usize = 4096;
+
+/// A point whose match will not change anymore.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OnlineMatch {
+    /// Position of the point among the points pushed to the matcher, counting from 0
+    pub index: usize,
+    pub matched: MatchedPoint,
+    /// Roads driven between the previous match and this one, excluding both their roads,
+    /// or [`None`] if this is the first match or the previous match cannot be reached from this one
+    pub roads: Option<Vec<Id>>,
+}
+
+/// The candidates of a point which has not been finalized yet.
+struct Layer {
+    index: usize,
+    observation: Observation,
+    candidates: Vec<Candidate>,
+    /// Best predecessor of each candidate, in the previous layer or in the anchor.
+    /// [`None`] for candidates that cannot be reached, or if there is nothing before this layer.
+    back: Vec<BackPointer>,
+}
+
+/// Matches a trajectory one point at a time, using the same hidden Markov model as [`hmm_match`](super::hmm_match).
+///
+/// A point is finalized once `lag` more points have been pushed after it,
+/// so the matcher holds at most `lag` points no matter how long the trip is.
+/// A larger lag delays matches, but lets later points correct the match of earlier points.
+///
+/// # Example
+/// ```
+/// use geo::wkt;
+/// use rusty_roads::{
+///     Direction, MatchConfig, OnlineMatcher, Road, RoadIndex, RoadNetwork, RoadWithNode, TimedPoint,
+/// };
+///
+/// let road = |id, geom| Road {
+///     id,
+///     geom,
+///     osm_id: id,
+///     code: 5113,
+///     direction: Direction::Bidirectional,
+///     maxspeed: 50,
+///     layer: 0,
+///     bridge: false,
+///     tunnel: false,
+/// };
+/// let roads = [
+///     road(0, wkt! {LINESTRING(10.000 57.0, 10.002 57.0)}),
+///     road(1, wkt! {LINESTRING(10.002 57.0, 10.004 57.0)}),
+/// ];
+/// let network: RoadNetwork<u16> = RoadNetwork::new(
+///     roads.iter().zip([(1, 2), (2, 3)]).map(|(road, (source, target))| RoadWithNode { road, source, target }),
+/// )
+/// .unwrap();
+/// let index = RoadIndex::from_ids_and_roads(&[0, 1], &[roads[0].geom.clone(), roads[1].geom.clone()]);
+///
+/// let mut matcher = OnlineMatcher::new(&index, &network, MatchConfig::default(), 1).unwrap();
+/// let fixes = wkt! {LINESTRING(10.0005 57.00005, 10.0015 57.00005, 10.0025 57.00005)};
+/// let mut matches = vec![];
+/// for (i, point) in fixes.points().enumerate() {
+///     matches.extend(matcher.push(TimedPoint::new(point, 10. * i as f64)).unwrap());
+/// }
+/// assert_eq!(matches.len(), 2, "the last point waits for another point");
+/// matches.extend(matcher.finish());
+/// assert_eq!(matches.iter().map(|m| m.matched.road).collect::<Vec<_>>(), vec![0, 0, 1]);
+/// ```
+pub struct OnlineMatcher<'i, 'n, 'a, Idx: IndexType> {
+    index: &'i RoadIndex,
+    router: Router<'n, 'a, Idx>,
+    config: MatchConfig,
+    lag: usize,
+    /// Points which have not been finalized yet, oldest first
+    window: VecDeque<Layer>,
+    /// Log probability of the most likely sequence of candidates ending in each candidate of the newest point in the window
+    scores: Vec<f64>,
+    /// The most recently finalized point and its match, which the oldest point in the window is routed from
+    anchor: Option<(Observation, Candidate)>,
+    pushed: usize,
+}
+
+impl<'i, 'n, 'a, Idx: IndexType> OnlineMatcher<'i, 'n, 'a, Idx> {
+    /// Creates a matcher finalizing points `lag` points after they are pushed.
+    ///
+    /// `index` must use the same road ids as `network`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if `config` is invalid.
+    pub fn new(
+        index: &'i RoadIndex,
+        network: &'n RoadNetwork<'a, Idx>,
+        config: MatchConfig,
+        lag: usize,
+    ) -> Result<Self, MatchConfigError> {
+        config.validate()?;
+        Ok(Self {
+            index,
+            router: Router::new(network),
+            config,
+            lag,
+            window: VecDeque::with_capacity(lag + 1),
+            scores: vec![],
+            anchor: None,
+            pushed: 0,
+        })
+    }
+
+    /// Adds the next point of the trajectory, returning the points that were finalized because of it.
+    ///
+    /// If the point cannot be reached from any candidate of the previous point, every pending point is finalized,
+    /// and matching starts over from this point.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the point has no candidate roads, in which case the point is skipped.
+    pub fn push(&mut self, point: TimedPoint) -> Result<Vec<OnlineMatch>, MatchError> {
+        self.push_observation(Observation {
+            point: point.point,
+            time: Some(point.time),
+            accuracy: point.accuracy,
+        })
+    }
+
+    /// Like [`OnlineMatcher::push`], for a point without a timestamp.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the point has no candidate roads, in which case the point is skipped.
+    pub fn push_point(&mut self, point: Point) -> Result<Vec<OnlineMatch>, MatchError> {
+        self.push_observation(Observation {
+            point,
+            time: None,
+            accuracy: None,
+        })
+    }
+
+    /// Finalizes every pending point, and resets the matcher for the next trajectory.
+    pub fn finish(&mut self) -> Vec<OnlineMatch> {
+        let finalized = self.flush();
+        self.anchor = None;
+        self.pushed = 0;
+        finalized
+    }
+
+    /// Number of points pushed but not finalized yet.
+    pub fn pending(&self) -> usize {
+        self.window.len()
+    }
+
+    fn push_observation(
+        &mut self,
+        observation: Observation,
+    ) -> Result<Vec<OnlineMatch>, MatchError> {
+        let index = self.pushed;
+        self.pushed += 1;
+        let candidates = candidates(self.index, observation.point, &self.config);
+        if candidates.is_empty() {
+            return Err(MatchError::NoCandidates(index));
+        }
+
+        let mut finalized = vec![];
+        let step = match (self.window.back(), &self.anchor) {
+            (Some(prev), _) => Some(step(
+                &mut self.router,
+                (&prev.observation, &prev.candidates, &self.scores),
+                (&observation, &candidates),
+                &self.config,
+            )),
+            (None, Some((anchor, candidate))) => Some(step(
+                &mut self.router,
+                (anchor, &[*candidate], &[0.]),
+                (&observation, &candidates),
+                &self.config,
+            )),
+            (None, None) => None,
+        };
+        let (scores, back) = match step {
+            Some(Step { scores, back, .. }) if scores.iter().any(|s| s.is_finite()) => {
+                (scores, back)
+            }
+            // either the first point, or the trajectory broke off, so start over from this point
+            _ => {
+                finalized.extend(self.flush());
+                self.anchor = None;
+                let scores = candidates
+                    .iter()
+                    .map(|c| emission(c, &self.config.hmm))
+                    .collect_vec();
+                (scores, vec![None; candidates.len()])
+            }
+        };
+
+        self.scores = scores;
+        self.window.push_back(Layer {
+            index,
+            observation,
+            candidates,
+            back,
+        });
+        while self.window.len() > self.lag {
+            finalized.push(self.finalize_oldest());
+        }
+        self.router.limit_cache(MAX_CACHED_PATHS);
+        Ok(finalized)
+    }
+
+    fn flush(&mut self) -> Vec<OnlineMatch> {
+        let mut finalized = Vec::with_capacity(self.window.len());
+        while !self.window.is_empty() {
+            finalized.push(self.finalize_oldest());
+        }
+        finalized
+    }
+
+    /// Picks the candidate of the oldest point on the most likely path to the newest point,
+    /// and rules out every candidate of the remaining points that is not reached through it.
+    fn finalize_oldest(&mut self) -> OnlineMatch {
+        let mut best = self
+            .scores
+            .iter()
+            .position_max_by(|fst, snd| fst.total_cmp(snd))
+            .expect("the newest point should have candidates");
+        for layer in self.window.iter().skip(1).rev() {
+            best = layer.back[best]
+                .as_ref()
+                .expect("a candidate on the most likely path should have a predecessor")
+                .0;
+        }
+
+        let oldest = self
+            .window
+            .pop_front()
+            .expect("there should be a point to finalize");
+        let candidate = oldest.candidates[best];
+        let roads = oldest.back[best].clone().map(|(_, roads)| roads);
+        self.anchor = Some((oldest.observation, candidate));
+
+        // the anchor is the only candidate left to come from
+        let mut reachable = vec![true];
+        let mut prev_choice = Some(best);
+        for layer in self.window.iter_mut() {
+            for pointer in layer.back.iter_mut() {
+                *pointer = pointer.take().and_then(|(i, roads)| match prev_choice {
+                    Some(choice) => (i == choice).then_some((0, roads)),
+                    None => reachable[i].then_some((i, roads)),
+                });
+            }
+            reachable = layer.back.iter().map(Option::is_some).collect();
+            prev_choice = None;
+        }
+        if !self.window.is_empty() {
+            for (score, reachable) in self.scores.iter_mut().zip(reachable) {
+                if !reachable {
+                    *score = f64::NEG_INFINITY;
+                }
+            }
+        }
+
+        OnlineMatch {
+            index: oldest.index,
+            matched: MatchedPoint::from_candidate(candidate, Some(best)),
+            roads,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::wkt;
+
+    use super::super::{fixtures, hmm_match_points, HmmParams};
+    use super::*;
+
+    #[test]
+    fn agrees_with_offline_matching() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+        let trajectory = wkt! {LINESTRING(
+            10.0005 57.00005,
+            10.0025 56.99995,
+            10.0035 57.00025,
+            10.0055 57.00005
+        )};
+
+        let offline = hmm_match_points(&trajectory, &index, &network, &HmmParams::default())
+            .expect("trajectory should match");
+        for lag in 0..=4 {
+            let mut matcher = OnlineMatcher::new(&index, &network, MatchConfig::default(), lag)
+                .expect("default config is valid");
+            let mut online = vec![];
+            for point in trajectory.points() {
+                online.extend(matcher.push_point(point).expect("point has candidates"));
+                assert!(matcher.pending() <= lag);
+            }
+            online.extend(matcher.finish());
+
+            assert_eq!(
+                online.iter().map(|m| m.index).collect_vec(),
+                vec![0, 1, 2, 3]
+            );
+            assert_eq!(online[0].roads, None);
+            if lag >= 2 {
+                let matched = online.into_iter().map(|m| m.matched).collect_vec();
+                assert_eq!(matched, offline, "lag {lag}");
+            }
+        }
+    }
+
+    #[test]
+    fn starts_over_when_disconnected() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+        let index = fixtures::index(&roads);
+        let config = MatchConfig {
+            max_radius: 20.,
+            ..Default::default()
+        };
+
+        let mut matcher = OnlineMatcher::new(&index, &network, config, 2).expect("config is valid");
+        let trajectory = wkt! {LINESTRING(10.0005 56.9999, 10.0015 56.9999, 10.0055 57.0005)};
+        let mut matches = vec![];
+        for point in trajectory.points() {
+            matches.extend(matcher.push_point(point).expect("point has candidates"));
+        }
+        assert_eq!(
+            matcher.push_point(Point::new(11., 58.)),
+            Err(MatchError::NoCandidates(3))
+        );
+        matches.extend(matcher.finish());
+
+        assert_eq!(
+            matches.iter().map(|m| m.matched.road).collect_vec(),
+            vec![0, 0, 3]
+        );
+        assert_eq!(matches[1].roads, Some(vec![]));
+        assert_eq!(matches[2].roads, None);
+    }
+}
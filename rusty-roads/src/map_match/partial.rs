@@ -0,0 +1,101 @@
+use std::ops::Range;
+
+use crate::Id;
+
+/// Why part of a trajectory could not be matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GapReason {
+    /// No road is within the search radius
+    NoCandidates,
+    /// Roads are nearby, but the closest point on every one of them is indeterminate
+    Indeterminate,
+    /// No route through the road network connects the matched parts on either side
+    Disconnected,
+    /// Every route connecting the matched parts on either side is too long to drive in the time between them
+    Implausible,
+}
+
+/// A part of a trajectory that could not be matched.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnmatchedSpan {
+    /// Indices of the unmatched points or segments.
+    ///
+    /// For [`GapReason::Disconnected`] and [`GapReason::Implausible`] every point is matched, so the range is empty,
+    /// and starts at the first index after the break.
+    pub span: Range<usize>,
+    pub reason: GapReason,
+}
+
+/// A part of a trajectory that was matched without interruption.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchedSpan<T> {
+    /// Index of the first matched point or segment
+    pub start: usize,
+    pub matched: Vec<T>,
+    /// The roads driven in this part, without consecutive repetitions
+    pub roads: Vec<Id>,
+}
+
+impl<T> MatchedSpan<T> {
+    /// Indices of the matched points or segments
+    pub fn span(&self) -> Range<usize> {
+        self.start..self.start + self.matched.len()
+    }
+}
+
+/// The matched and unmatched parts of a trajectory, each ordered along the trajectory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PartialMatch<T> {
+    pub matched: Vec<MatchedSpan<T>>,
+    pub unmatched: Vec<UnmatchedSpan>,
+}
+
+impl<T> Default for PartialMatch<T> {
+    fn default() -> Self {
+        Self {
+            matched: vec![],
+            unmatched: vec![],
+        }
+    }
+}
+
+impl<T> PartialMatch<T> {
+    /// Whether the whole trajectory was matched in one piece
+    pub fn is_complete(&self) -> bool {
+        self.unmatched.is_empty()
+    }
+
+    /// Number of matched points or segments
+    pub fn matched_count(&self) -> usize {
+        self.matched.iter().map(|m| m.matched.len()).sum()
+    }
+
+    pub(crate) fn push_matched(&mut self, start: usize, matched: Vec<T>, roads: Vec<Id>) {
+        if !matched.is_empty() {
+            self.matched.push(MatchedSpan {
+                start,
+                matched,
+                roads,
+            });
+        }
+    }
+
+    /// Records `index` as unmatched, extending the previous gap if it ends right before it for the same reason.
+    pub(crate) fn push_unmatched(&mut self, index: usize, reason: GapReason) {
+        match self.unmatched.last_mut() {
+            Some(gap) if gap.reason == reason && gap.span.end == index => gap.span.end += 1,
+            _ => self.unmatched.push(UnmatchedSpan {
+                span: index..index + 1,
+                reason,
+            }),
+        }
+    }
+
+    /// Records a break in the trajectory right before `index`.
+    pub(crate) fn push_break(&mut self, index: usize, reason: GapReason) {
+        self.unmatched.push(UnmatchedSpan {
+            span: index..index,
+            reason,
+        });
+    }
+}
@@ -0,0 +1,279 @@
+use geo::{Distance, Euclidean, Length};
+use geo_types::{Coord, LineString};
+use itertools::Itertools;
+use petgraph::matrix_graph::IndexType;
+
+use crate::{NodeId, RoadNetwork, RoadWithNode};
+
+use super::router::{forward, Router};
+use super::{MatchError, MatchedPoint};
+
+/// The roads driven along a matched trajectory, and the geometry of the drive.
+#[derive(Debug, Clone)]
+pub struct Reconstruction<'a> {
+    /// Every road driven in order, with `source` and `target` in the direction it was driven,
+    /// so the target of a road is the source of the next one.
+    pub roads: Vec<RoadWithNode<'a>>,
+    /// Follows the geometry of the roads from the first matched point to the last
+    pub geometry: LineString<f64>,
+}
+
+/// Fills in the roads driven between consecutive matched points using [`RoadNetwork::path_find`],
+/// which recovers whole roads skipped by sparsely sampled trajectories.
+///
+/// `matched` can come from any matcher, e.g. [`hmm_match_points`](super::hmm_match_points), or the start and end points
+/// of the segments matched by [`segment_match_detailed`](super::segment_match_detailed). The route between two points
+/// respects the direction of the roads, and stays on the road when both points are on the same road and the road
+/// may be driven from one to the other.
+///
+/// # Errors
+///
+/// This function will return an error if `matched` is empty, if a point is matched to a road that is not part of `network`,
+/// or if there is no route between two consecutive points.
+pub fn reconstruct_route<'a, Idx: IndexType>(
+    matched: &[MatchedPoint],
+    network: &RoadNetwork<'a, Idx>,
+) -> Result<Reconstruction<'a>, MatchError> {
+    let road_of = |id| network.road(id).ok_or(MatchError::UnknownRoad(id));
+    let first = matched.first().ok_or(MatchError::EmptyTrajectory)?;
+    let first_road = road_of(first.road)?;
+
+    let mut router = Router::new(network);
+    let mut roads = vec![];
+    let mut coords = vec![first.point.0];
+    for (i, (a, b)) in matched.iter().tuple_windows().enumerate() {
+        let (road_a, road_b) = (road_of(a.road)?, road_of(b.road)?);
+        let route = router
+            .route(&a.candidate(), &b.candidate())
+            .ok_or(MatchError::Disconnected(i, i + 1))?;
+        let (geom_a, geom_b) = (&road_a.road.geom, &road_b.road.geom);
+
+        let Some((exit, entry)) = route.nodes else {
+            if a.fraction != b.fraction {
+                let towards = match b.fraction > a.fraction {
+                    true => road_a.target,
+                    false => road_a.source,
+                };
+                roads.push(towards_node(road_a, towards));
+            }
+            coords.extend(vertices_between(geom_a, a.fraction, b.fraction));
+            coords.push(b.point.0);
+            continue;
+        };
+
+        let exit_end = end_at(road_a, exit);
+        coords.extend(vertices_between(geom_a, a.fraction, exit_end));
+        coords.extend(endpoint(geom_a, exit_end));
+        roads.push(towards_node(road_a, exit));
+
+        let mut node = exit;
+        for id in route.roads {
+            let road = from_node(road_of(id)?, node);
+            node = road.target;
+            let start = end_at(&road, road.source);
+            coords.extend(vertices_between(&road.road.geom, start, 1. - start));
+            coords.extend(endpoint(&road.road.geom, 1. - start));
+            roads.push(road);
+        }
+
+        let entry_end = end_at(road_b, entry);
+        coords.extend(endpoint(geom_b, entry_end));
+        coords.extend(vertices_between(geom_b, entry_end, b.fraction));
+        coords.push(b.point.0);
+        roads.push(from_node(road_b, entry));
+    }
+
+    if roads.is_empty() {
+        roads.push(first_road.clone());
+    }
+    roads.dedup_by(|b, a| a.road.id == b.road.id && a.source == b.source && a.target == b.target);
+    coords.dedup();
+    Ok(Reconstruction {
+        roads,
+        geometry: LineString::new(coords),
+    })
+}
+
+/// `road` driven in the direction that ends at `node`.
+fn towards_node<'a>(road: &RoadWithNode<'a>, node: NodeId) -> RoadWithNode<'a> {
+    match road.target == node && (road.source != node || forward(road.road.direction)) {
+        true => road.clone(),
+        false => reversed(road),
+    }
+}
+
+/// `road` driven in the direction that starts at `node`.
+fn from_node<'a>(road: &RoadWithNode<'a>, node: NodeId) -> RoadWithNode<'a> {
+    match road.source == node && (road.target != node || forward(road.road.direction)) {
+        true => road.clone(),
+        false => reversed(road),
+    }
+}
+
+fn reversed<'a>(road: &RoadWithNode<'a>) -> RoadWithNode<'a> {
+    RoadWithNode {
+        road: road.road,
+        source: road.target,
+        target: road.source,
+    }
+}
+
+/// The fraction of the geometry of `road` at which `node` lies, i.e. 0 for its source and 1 for its target.
+fn end_at(road: &RoadWithNode, node: NodeId) -> f64 {
+    match node == road.target && node != road.source {
+        true => 1.,
+        false => match node == road.source && node == road.target && forward(road.road.direction) {
+            true => 1.,
+            false => 0.,
+        },
+    }
+}
+
+/// The first coordinate of `line` if `fraction` is 0, and otherwise the last.
+fn endpoint(line: &LineString<f64>, fraction: f64) -> Option<Coord> {
+    match fraction == 0. {
+        true => line.0.first().copied(),
+        false => line.0.last().copied(),
+    }
+}
+
+/// The vertices of `line` strictly between the fractions `from` and `to` of its length, ordered from `from` to `to`.
+fn vertices_between(line: &LineString<f64>, from: f64, to: f64) -> Vec<Coord> {
+    let total = Euclidean.length(line);
+    if total == 0. {
+        return vec![];
+    }
+    let (low, high) = (from.min(to), from.max(to));
+    let mut walked = 0.;
+    let mut vertices = vec![];
+    for (prev, coord) in line.0.iter().zip(line.0.iter().skip(1)) {
+        walked += Euclidean.distance(*prev, *coord);
+        let fraction = walked / total;
+        if low < fraction && fraction < high {
+            vertices.push(*coord);
+        }
+    }
+    if from > to {
+        vertices.reverse();
+    }
+    vertices
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::wkt;
+    use geo_types::Point;
+
+    use super::super::fixtures;
+    use super::*;
+    use crate::{Direction, Id, Road};
+
+    fn at(road: Id, x: f64, fraction: f64) -> MatchedPoint {
+        MatchedPoint {
+            road,
+            point: Point::new(x, 57.0),
+            fraction,
+            distance: 0.,
+            rank: Some(0),
+        }
+    }
+
+    fn ids(reconstruction: &Reconstruction) -> Vec<(Id, NodeId, NodeId)> {
+        reconstruction
+            .roads
+            .iter()
+            .map(|r| (r.road.id, r.source, r.target))
+            .collect()
+    }
+
+    #[test]
+    fn fills_in_skipped_roads() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+
+        let matched = [
+            at(0, 10.0005, 0.25),
+            at(0, 10.0015, 0.75),
+            at(2, 10.0055, 0.75),
+        ];
+        let route = reconstruct_route(&matched, &network).expect("points are connected");
+        assert_eq!(ids(&route), vec![(0, 1, 2), (1, 2, 3), (2, 3, 4)]);
+        assert_eq!(
+            route.geometry,
+            wkt! {LINESTRING(10.0005 57.0, 10.0015 57.0, 10.002 57.0, 10.004 57.0, 10.0055 57.0)}
+        );
+    }
+
+    #[test]
+    fn follows_direction_of_travel() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+
+        // backwards along the chain, turning around on the middle road
+        let matched = [
+            at(2, 10.0055, 0.75),
+            at(1, 10.0025, 0.25),
+            at(1, 10.0035, 0.75),
+        ];
+        let route = reconstruct_route(&matched, &network).expect("points are connected");
+        assert_eq!(ids(&route), vec![(2, 4, 3), (1, 3, 2), (1, 2, 3)]);
+        assert_eq!(
+            route.geometry,
+            wkt! {LINESTRING(10.0055 57.0, 10.004 57.0, 10.0025 57.0, 10.0035 57.0)}
+        );
+    }
+
+    #[test]
+    fn goes_around_one_way_roads() {
+        let mut one_way =
+            fixtures::road(0, wkt! {LINESTRING(10.000 57.0, 10.001 57.0, 10.002 57.0)});
+        one_way.direction = Direction::Forward;
+        let roads: Vec<(Road, NodeId, NodeId)> = vec![
+            (one_way, 1, 2),
+            (
+                fixtures::road(1, wkt! {LINESTRING(10.002 57.0, 10.001 57.001)}),
+                2,
+                3,
+            ),
+            (
+                fixtures::road(2, wkt! {LINESTRING(10.001 57.001, 10.000 57.0)}),
+                3,
+                1,
+            ),
+        ];
+        let network = fixtures::network(&roads);
+
+        // driving west is only possible by going around, so the one-way road must be driven to its end
+        let matched = [at(0, 10.0015, 0.75), at(0, 10.0005, 0.25)];
+        let route = reconstruct_route(&matched, &network).expect("points are connected");
+        assert_eq!(
+            ids(&route),
+            vec![(0, 1, 2), (1, 2, 3), (2, 3, 1), (0, 1, 2)]
+        );
+        assert_eq!(
+            route.geometry,
+            wkt! {LINESTRING(10.0015 57.0, 10.002 57.0, 10.001 57.001, 10.000 57.0, 10.0005 57.0)}
+        );
+    }
+
+    #[test]
+    fn reports_missing_routes() {
+        let roads = fixtures::roads();
+        let network = fixtures::network(&roads);
+
+        assert_eq!(
+            reconstruct_route(&[], &network).map(|r| r.roads.len()),
+            Err(MatchError::EmptyTrajectory)
+        );
+        let unknown = [at(0, 10.0005, 0.25), at(42, 10.0015, 0.75)];
+        assert_eq!(
+            reconstruct_route(&unknown, &network).map(|r| r.roads.len()),
+            Err(MatchError::UnknownRoad(42))
+        );
+        let disconnected = [at(0, 10.0005, 0.25), at(3, 10.0015, 0.25)];
+        assert_eq!(
+            reconstruct_route(&disconnected, &network).map(|r| r.roads.len()),
+            Err(MatchError::Disconnected(0, 1))
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+
+use geo::{Haversine, Length};
+use itertools::Itertools;
+use petgraph::matrix_graph::IndexType;
+
+use crate::{Direction, Id, Meter, NodeId, NonNegativef64, Road, RoadNetwork};
+
+use super::candidate::Candidate;
+
+/// The way from one candidate to another through the road network.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Route {
+    /// Distance in meters driven along the roads
+    pub distance: Meter,
+    /// Roads driven between the road of the first and the road of the second candidate
+    pub roads: Vec<Id>,
+    /// The node the road of the first candidate is left through, and the node the road of the second candidate is entered through,
+    /// or [`None`] if the route stays on the road of both candidates
+    pub nodes: Option<(NodeId, NodeId)>,
+}
+
+/// Length in meters and roads of a shortest path between two nodes
+type Path = (Meter, Vec<Id>);
+
+/// Finds routes between candidates, remembering the shortest paths it has found between nodes.
+pub(crate) struct Router<'n, 'a, Idx: IndexType> {
+    network: &'n RoadNetwork<'a, Idx>,
+    paths: HashMap<(NodeId, NodeId), Option<Path>>,
+}
+
+impl<'n, 'a, Idx: IndexType> Router<'n, 'a, Idx> {
+    pub fn new(network: &'n RoadNetwork<'a, Idx>) -> Self {
+        Self {
+            network,
+            paths: HashMap::new(),
+        }
+    }
+
+    /// Finds the shortest route from `from` to `to` respecting the direction of the roads.
+    ///
+    /// Returns [`None`] if either road is not part of the network, or if there is no route.
+    pub fn route(&mut self, from: &Candidate, to: &Candidate) -> Option<Route> {
+        let a = self.network.road(from.road)?;
+        let b = self.network.road(to.road)?;
+
+        if from.road == to.road {
+            let along = to.fraction - from.fraction;
+            let direction = a.road.direction;
+            if (along >= 0. && forward(direction)) || (along <= 0. && backward(direction)) {
+                return Some(Route {
+                    distance: along.abs() * road_length(a.road),
+                    roads: vec![],
+                    nodes: None,
+                });
+            }
+        }
+
+        let (length_a, length_b) = (road_length(a.road), road_length(b.road));
+        let exits = [
+            forward(a.road.direction).then_some((a.target, (1. - from.fraction) * length_a)),
+            backward(a.road.direction).then_some((a.source, from.fraction * length_a)),
+        ];
+        let entries = [
+            forward(b.road.direction).then_some((b.source, to.fraction * length_b)),
+            backward(b.road.direction).then_some((b.target, (1. - to.fraction) * length_b)),
+        ];
+
+        exits
+            .into_iter()
+            .flatten()
+            .cartesian_product(entries.into_iter().flatten())
+            .filter_map(|((exit, to_exit), (entry, from_entry))| {
+                let (distance, roads) = self.path(exit, entry)?;
+                Some(Route {
+                    distance: to_exit + distance + from_entry,
+                    roads,
+                    nodes: Some((exit, entry)),
+                })
+            })
+            .min_by(|fst, snd| fst.distance.total_cmp(&snd.distance))
+    }
+
+    /// The road with the given id, or [`None`] if it is not in the network.
+    pub fn road(&self, id: Id) -> Option<&'a Road> {
+        self.network.road(id).map(|r| r.road)
+    }
+
+    /// The highest speed limit in meters per second on any road of `route` from `from` to `to`,
+    /// or [`None`] if none of them has a known speed limit.
+    pub fn max_speed(&self, from: &Candidate, to: &Candidate, route: &Route) -> Option<f64> {
+        [from.road, to.road]
+            .iter()
+            .chain(route.roads.iter())
+            .filter_map(|id| self.network.road(*id))
+            .map(|r| r.road.maxspeed)
+            .filter(|maxspeed| *maxspeed > 0)
+            .max()
+            .map(|kmh| f64::from(kmh) / 3.6)
+    }
+
+    /// Forgets every shortest path found so far if more than `max_paths` are remembered.
+    pub fn limit_cache(&mut self, max_paths: usize) {
+        if self.paths.len() > max_paths {
+            self.paths.clear();
+        }
+    }
+
+    fn path(&mut self, source: NodeId, target: NodeId) -> Option<Path> {
+        if source == target {
+            return Some((0., vec![]));
+        }
+        let network = self.network;
+        self.paths
+            .entry((source, target))
+            .or_insert_with(|| {
+                let (cost, roads) = network.path_find(
+                    source,
+                    target,
+                    |road| {
+                        NonNegativef64::try_from(road_length(road))
+                            .expect("length of a road should be nonnegative")
+                    },
+                    |_| NonNegativef64::try_from(0.).expect("zero is nonnegative"),
+                )?;
+                Some((cost.into(), roads.iter().map(|r| r.road.id).collect()))
+            })
+            .clone()
+    }
+}
+
+/// Length of a road in meters
+pub(crate) fn road_length(road: &Road) -> Meter {
+    Haversine.length(&road.geom)
+}
+
+/// Whether a road can be driven in the direction of its geometry
+pub(crate) fn forward(direction: Direction) -> bool {
+    matches!(direction, Direction::Forward | Direction::Bidirectional)
+}
+
+/// Whether a road can be driven against the direction of its geometry
+pub(crate) fn backward(direction: Direction) -> bool {
+    matches!(direction, Direction::Backward | Direction::Bidirectional)
+}
@@ -0,0 +1,208 @@
+use geo::{Distance, Euclidean};
+use geo_types::{Line, LineString, Point};
+
+use crate::{Direction, Id, Queryable, RoadKey, Roads};
+
+use super::candidate::Candidate;
+use super::config::DistanceMetric;
+use super::map_match::line_similarity;
+use super::router::{backward, forward};
+
+/// Weights of the terms combined into the score of a candidate road for a trajectory segment, where a lower score is better.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScoringWeights {
+    /// Per meter from the endpoints of the segment to the road
+    pub distance: f64,
+    /// Per unit of difference in direction between the segment and the road,
+    /// which ranges from 0 when they agree, over sqrt(2) at a right angle, to 2 when they are opposite
+    pub heading: f64,
+    /// Added when the segment drives a one-way road against its direction
+    pub wrong_way: f64,
+    /// Multiplies the distance when both endpoints of the segment are matched to the same point on the road,
+    /// which only applies to the distance-only score of [`segment_match`](super::segment_match)
+    pub same_point: f64,
+    /// Per level between the road and the road matched to the previous segment, where bridges are above and tunnels below the ground,
+    /// which only applies to [`segment_match_scored`](super::segment_match_scored)
+    pub segment_layer_penalty: f64,
+}
+
+impl Default for ScoringWeights {
+    fn default() -> Self {
+        Self {
+            distance: 1.0,
+            heading: 20.0,
+            wrong_way: 100.0,
+            same_point: 2.0,
+            segment_layer_penalty: 10.0,
+        }
+    }
+}
+
+/// The direction of the road with the given id, or [`Direction::Bidirectional`] if it is not in `roads`.
+pub(crate) fn direction_of(roads: &Roads, id: Id) -> Direction {
+    roads
+        .find_index(&RoadKey(id))
+        .map_or(Direction::Bidirectional, |i| roads.direction[i])
+}
+
+/// Scores how well `segment` matches a road with the given geometry and direction, measuring distances with `metric`.
+///
+/// Returns [`None`] if the closest point on the road to either endpoint of the segment is indeterminate.
+pub(crate) fn score_candidate(
+    segment: &Line,
+    road: &LineString<f64>,
+    direction: Direction,
+    weights: &ScoringWeights,
+    metric: DistanceMetric,
+) -> Option<f64> {
+    let start = Candidate::project(0, road, segment.start_point(), metric)?;
+    let end = Candidate::project(0, road, segment.end_point(), metric)?;
+    let distance = start.distance + end.distance;
+
+    // a stationary segment has no direction to compare
+    if segment.start == segment.end {
+        return Some(weights.distance * distance);
+    }
+
+    let (travelled, along_geometry) = match start.fraction == end.fraction {
+        false => (
+            Line::new(start.point, end.point),
+            end.fraction > start.fraction,
+        ),
+        // both endpoints are matched to the same point, e.g. when the segment crosses the road,
+        // so compare with the part of the road at that point instead
+        true => {
+            let local = local_segment(road, start.point)?;
+            let reversed = Line::new(local.end, local.start);
+            match line_similarity(segment, &local) <= line_similarity(segment, &reversed) {
+                true => (local, true),
+                false => (reversed, false),
+            }
+        }
+    };
+    let heading = line_similarity(segment, &travelled);
+    let wrong_way = match along_geometry {
+        true => !forward(direction),
+        false => !backward(direction),
+    };
+
+    Some(
+        weights.distance * distance
+            + weights.heading * heading
+            + if wrong_way { weights.wrong_way } else { 0. },
+    )
+}
+
+/// The segment of `road` closest to `point`, in the direction of the road's geometry.
+fn local_segment(road: &LineString<f64>, point: Point) -> Option<Line> {
+    road.lines()
+        .filter(|l| l.start != l.end)
+        .min_by(|fst, snd| {
+            Euclidean
+                .distance(fst, &point)
+                .total_cmp(&Euclidean.distance(snd, &point))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::{wkt, Haversine};
+
+    use super::super::{fixtures, segment_match_detailed, segment_match_scored};
+    use super::*;
+    use crate::{Insertable, RoadIndex};
+
+    /// A one-way road heading east, and a bidirectional road running parallel 11 meters to the south of it
+    fn roads() -> Roads {
+        let mut one_way = fixtures::road(0, wkt! {LINESTRING(10.000 57.0, 10.004 57.0)});
+        one_way.direction = Direction::Forward;
+        let two_way = fixtures::road(1, wkt! {LINESTRING(10.000 56.9999, 10.004 56.9999)});
+        let mut roads = Roads::default();
+        roads.insert_many([one_way, two_way]);
+        roads
+    }
+
+    #[test]
+    fn penalizes_driving_against_one_way() {
+        let roads = roads();
+        let index = RoadIndex::from_ids_and_roads(roads.id(), &roads.geom);
+
+        // heading west, slightly closer to the one-way road
+        let traj = wkt! {LINESTRING(10.003 56.99996, 10.001 56.99996)};
+        let matched =
+            segment_match_scored(traj.lines(), &index, &roads, &ScoringWeights::default())
+                .expect("should match");
+        assert_eq!(matched[0].start.road, 1);
+
+        // heading east, the one-way road is fine
+        let traj = wkt! {LINESTRING(10.001 56.99996, 10.003 56.99996)};
+        let matched =
+            segment_match_scored(traj.lines(), &index, &roads, &ScoringWeights::default())
+                .expect("should match");
+        assert_eq!(matched[0].start.road, 0);
+    }
+
+    #[test]
+    fn prefers_roads_with_same_heading() {
+        let roads: Roads = [
+            fixtures::road(0, wkt! {LINESTRING(10.000 57.0, 10.004 57.0)}),
+            fixtures::road(1, wkt! {LINESTRING(10.0021 56.998, 10.0021 57.002)}),
+        ]
+        .into_iter()
+        .collect();
+        let index = RoadIndex::from_ids_and_roads(roads.id(), &roads.geom);
+
+        // heading east 13 meters north of the first road, crossing the second road
+        let traj = wkt! {LINESTRING(10.0020 57.00012, 10.0022 57.00012)};
+        let unscored = segment_match_detailed(traj.lines(), &index).expect("should match");
+        assert_eq!(unscored[0].start.road, 1);
+
+        let scored = segment_match_scored(traj.lines(), &index, &roads, &ScoringWeights::default())
+            .expect("should match");
+        assert_eq!(scored[0].start.road, 0);
+    }
+
+    #[test]
+    fn scored_segments_stay_below_bridge() {
+        let mut bridge = fixtures::road(1, wkt! {LINESTRING(10.001 57.00004, 10.003 57.00004)});
+        (bridge.layer, bridge.bridge) = (0, true);
+        let roads: Roads = [
+            fixtures::road(0, wkt! {LINESTRING(10.000 57.0, 10.004 57.0)}),
+            bridge,
+        ]
+        .into_iter()
+        .collect();
+        let index = RoadIndex::from_ids_and_roads(roads.id(), &roads.geom);
+
+        // the middle segment is slightly closer to the bridge than to the street below it
+        let traj = wkt! {LINESTRING(10.0005 57.00001, 10.0015 57.000025, 10.0025 57.000025)};
+        let weights = ScoringWeights {
+            segment_layer_penalty: 0.,
+            ..Default::default()
+        };
+        let matched =
+            segment_match_scored(traj.lines(), &index, &roads, &weights).expect("should match");
+        assert_eq!(matched[1].start.road, 1);
+
+        let matched =
+            segment_match_scored(traj.lines(), &index, &roads, &ScoringWeights::default())
+                .expect("should match");
+        assert!(matched.iter().all(|s| s.start.road == 0));
+    }
+
+    #[test]
+    fn stationary_segment_scores_distance_only() {
+        let road = wkt! {LINESTRING(10.000 57.0, 10.004 57.0)};
+        let point = wkt! {POINT(10.002 57.0001)};
+        let score = score_candidate(
+            &Line::new(point.0, point.0),
+            &road,
+            Direction::Forward,
+            &ScoringWeights::default(),
+            DistanceMetric::Haversine,
+        )
+        .expect("closest point should be determinate");
+        let expected = 2. * Haversine.distance(point, Point::new(10.002, 57.0));
+        assert!((score - expected).abs() < 1e-6);
+    }
+}
@@ -1,5 +1,6 @@
 use crate::Id;
 
+#[derive(Debug, Default, Clone)]
 pub struct Anonymities {
     pub road_id: Vec<Id>,
     pub current_k: Vec<f64>,
@@ -0,0 +1,263 @@
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::sync::OnceLock;
+
+/// Hash index from the values of a key column to the position of the first row holding them.
+///
+/// The index is built on first use, e.g. after a table is read from Parquet, and is kept up to date by
+/// [`Insertable`](super::Insertable), [`Deleteable`](super::Deleteable), [`Updatable`](super::Updatable)
+/// and the setters of the table. Indexed columns are private to their table, as a value changed in place would go unnoticed.
+/// If rows are pushed or removed by hand instead, lookups fall back to a linear scan until the next insert or delete
+/// rebuilds the index.
+#[derive(Clone, Default)]
+pub struct KeyIndex<K> {
+    positions: OnceLock<Positions<K>>,
+}
+
+/// Removed rows are only subtracted from the positions after this many removals, so a removal does not touch every position
+const MAX_PENDING_REMOVALS: usize = 1024;
+
+#[derive(Clone)]
+struct Positions<K> {
+    /// Number of rows indexed
+    rows: usize,
+    /// Position of the first row holding each key, counting the rows in `removed`
+    map: HashMap<K, usize>,
+    /// Sorted positions of removed rows, counting each other
+    removed: Vec<usize>,
+}
+
+impl<K: Hash + Eq + Clone> Positions<K> {
+    fn new<T: Borrow<K>, I: Iterator<Item = T>>(keys: I) -> Self {
+        let mut map = HashMap::with_capacity(keys.size_hint().0);
+        let mut rows = 0;
+        for key in keys {
+            map.entry(key.borrow().clone()).or_insert(rows);
+            rows += 1;
+        }
+        Self {
+            rows,
+            map,
+            removed: vec![],
+        }
+    }
+
+    /// Current position of the row stored at `stored`
+    fn current(&self, stored: usize) -> usize {
+        stored - self.removed.partition_point(|r| *r < stored)
+    }
+
+    /// Stored position of the row currently at `position`
+    fn stored(&self, position: usize) -> usize {
+        let mut stored = position;
+        loop {
+            let next = position + self.removed.partition_point(|r| *r <= stored);
+            if next == stored {
+                return stored;
+            }
+            stored = next;
+        }
+    }
+
+    fn subtract_removed(&mut self) {
+        let removed = &self.removed;
+        for stored in self.map.values_mut() {
+            *stored -= removed.partition_point(|r| *r < *stored);
+        }
+        self.removed.clear();
+    }
+}
+
+impl<K: Hash + Eq + Clone> KeyIndex<K> {
+    /// Position of the first row where the column `keys` holds `key`
+    pub fn find<Q, T, I>(&self, key: &Q, mut keys: I) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+        T: Borrow<K>,
+        I: ExactSizeIterator<Item = T> + Clone,
+    {
+        let positions = self.positions.get_or_init(|| Positions::new(keys.clone()));
+        match positions.rows == keys.len() {
+            true => positions.map.get(key).map(|s| positions.current(*s)),
+            false => keys.position(|k| k.borrow().borrow() == key),
+        }
+    }
+
+    /// Indexes `key` at the position of a new last row, where the column held `keys` before the row was pushed.
+    pub(crate) fn push<T, I>(&mut self, key: K, keys: I)
+    where
+        T: Borrow<K>,
+        I: ExactSizeIterator<Item = T> + Clone,
+    {
+        let positions = self.synced(keys);
+        let stored = positions.rows + positions.removed.len();
+        positions.map.entry(key).or_insert(stored);
+        positions.rows += 1;
+    }
+
+    /// Unindexes the row at `position` holding `key`, where the column holds `keys` after the row was removed.
+    pub(crate) fn remove<T, I>(&mut self, position: usize, key: &K, mut keys: I)
+    where
+        T: Borrow<K>,
+        I: ExactSizeIterator<Item = T> + Clone,
+    {
+        let positions = match self.positions.get_mut() {
+            Some(positions) if positions.rows == keys.len() + 1 => positions,
+            _ => {
+                self.positions = OnceLock::from(Positions::new(keys));
+                return;
+            }
+        };
+        let stored = positions.stored(position);
+        if positions.map.get(key) == Some(&stored) {
+            positions.map.remove(key);
+        }
+        let at = positions.removed.partition_point(|r| *r < stored);
+        positions.removed.insert(at, stored);
+        positions.rows -= 1;
+
+        // a duplicate of the removed key may now come first, which is only possible if some key is held by several rows
+        if positions.rows > positions.map.len() && !positions.map.contains_key(key) {
+            if let Some(i) = keys.position(|k| k.borrow() == key) {
+                let stored = positions.stored(i);
+                positions.map.insert(key.clone(), stored);
+            }
+        }
+        if positions.removed.len() >= MAX_PENDING_REMOVALS {
+            positions.subtract_removed();
+        }
+    }
+
+    /// Reindexes the row at `position` from `old` to `new`, where the column holds `keys` after the row was changed.
+    pub(crate) fn replace<T, I>(&mut self, position: usize, old: &K, new: K, mut keys: I)
+    where
+        T: Borrow<K>,
+        I: ExactSizeIterator<Item = T> + Clone,
+    {
+        let positions = match self.positions.get_mut() {
+            Some(positions) if positions.rows == keys.len() => positions,
+            _ => {
+                self.positions = OnceLock::from(Positions::new(keys));
+                return;
+            }
+        };
+        if *old == new {
+            return;
+        }
+        let stored = positions.stored(position);
+        if positions.map.get(old) == Some(&stored) {
+            positions.map.remove(old);
+        }
+        match positions.map.get(&new).map(|s| positions.current(*s)) {
+            Some(first) if first < position => {}
+            _ => {
+                positions.map.insert(new, stored);
+            }
+        }
+        // a duplicate of the old key may now come first
+        if positions.rows > positions.map.len() && !positions.map.contains_key(old) {
+            if let Some(i) = keys.position(|k| k.borrow() == old) {
+                let stored = positions.stored(i);
+                positions.map.insert(old.clone(), stored);
+            }
+        }
+    }
+
+    /// The positions, rebuilt from `keys` if they are missing or out of date
+    fn synced<T, I>(&mut self, keys: I) -> &mut Positions<K>
+    where
+        T: Borrow<K>,
+        I: ExactSizeIterator<Item = T> + Clone,
+    {
+        if self.positions.get().is_none_or(|p| p.rows != keys.len()) {
+            self.positions = OnceLock::from(Positions::new(keys));
+        }
+        self.positions
+            .get_mut()
+            .expect("positions were just initialized")
+    }
+}
+
+impl<K> fmt::Debug for KeyIndex<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyIndex")
+            .field("rows", &self.positions.get().map(|p| p.rows))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stays_consistent() {
+        let mut column = vec![5, 7, 5, 9];
+        let mut index = KeyIndex::default();
+        assert_eq!(index.find(&5, column.iter().copied()), Some(0));
+        assert_eq!(index.find(&9, column.iter().copied()), Some(3));
+
+        index.push(11, column.iter().copied());
+        column.push(11);
+        assert_eq!(index.find(&11, column.iter().copied()), Some(4));
+
+        // the duplicate of the removed key takes its place
+        column.remove(0);
+        index.remove(0, &5, column.iter().copied());
+        for (i, key) in column.iter().enumerate().rev() {
+            let first = column.iter().position(|k| k == key);
+            assert_eq!(index.find(key, column.iter().copied()), first, "row {i}");
+        }
+        assert_eq!(index.find(&5, column.iter().copied()), Some(1));
+
+        // pushed by hand, so the index is out of date and falls back to scanning
+        column.push(13);
+        assert_eq!(index.find(&13, column.iter().copied()), Some(4));
+        index.push(15, column.iter().copied());
+        column.push(15);
+        assert_eq!(index.find(&15, column.iter().copied()), Some(5));
+        assert_eq!(index.find(&42, column.iter().copied()), None);
+    }
+
+    #[test]
+    fn replaces_keys() {
+        let mut column = vec![1, 2, 3, 2];
+        let mut index = KeyIndex::default();
+        column.remove(0);
+        index.remove(0, &1, column.iter().copied());
+
+        column[0] = 7;
+        index.replace(0, &2, 7, column.iter().copied());
+        column[2] = 3;
+        index.replace(2, &2, 3, column.iter().copied());
+        for key in [1, 2, 3, 7] {
+            let first = column.iter().position(|k| *k == key);
+            assert_eq!(index.find(&key, column.iter().copied()), first, "key {key}");
+        }
+    }
+
+    #[test]
+    fn subtracts_many_removals() {
+        let mut column: Vec<u64> = (0..3000).map(|i| i % 2500).collect();
+        let mut index = KeyIndex::default();
+        assert_eq!(index.find(&10, column.iter().copied()), Some(10));
+        for i in 0..MAX_PENDING_REMOVALS + 100 {
+            let position = (i * 7) % column.len();
+            let key = column.remove(position);
+            index.remove(position, &key, column.iter().copied());
+            if i % 97 == 0 {
+                for key in [key, 0, 1234, 2499] {
+                    let first = column.iter().position(|k| *k == key);
+                    assert_eq!(index.find(&key, column.iter().copied()), first);
+                }
+            }
+        }
+        for key in 0..2500 {
+            let first = column.iter().position(|k| *k == key);
+            assert_eq!(index.find(&key, column.iter().copied()), first, "key {key}");
+        }
+    }
+}
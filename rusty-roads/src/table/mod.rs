@@ -1,3 +1,5 @@
+use thiserror::Error;
+
 pub mod name;
 pub use name::*;
 pub mod road;
@@ -12,6 +14,10 @@ pub mod anonymities;
 pub use anonymities::*;
 pub mod trajectories;
 pub use trajectories::*;
+pub mod road_embedding;
+pub use road_embedding::*;
+mod key_index;
+pub use key_index::*;
 
 /// Type T is insertable into Self
 pub trait Insertable<Data> {
@@ -35,6 +41,29 @@ pub trait Deleteable<Key> {
     }
 }
 
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum UpdateError {
+    #[error("no row has the given key")]
+    NotFound,
+    #[error("another row already has the same {0}")]
+    Duplicate(&'static str),
+    #[error("the key is assigned on insert and cannot be changed")]
+    KeyChanged,
+}
+
+/// Rows of Self can be updated by Key
+pub trait Updatable<Key> {
+    type Row;
+    /// Replaces the row with Key by Row, and returns the old row
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if no row has Key,
+    /// if Row has the key or a unique value of another row,
+    /// or if Row changes a key that is assigned on insert.
+    fn update(&mut self, key: &Key, row: Self::Row) -> Result<Self::Row, UpdateError>;
+}
+
 /// Type Key is queryable from Self
 pub trait Queryable<Key> {
     /// Find the index of T in Self
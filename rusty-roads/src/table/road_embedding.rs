@@ -0,0 +1,40 @@
+use std::collections::BTreeMap;
+
+use comms::Parquet;
+
+use crate::Id;
+
+/// Learned vectors of roads, with one value per row so they can be written as Parquet.
+#[derive(Debug, Default, Clone, PartialEq, Parquet)]
+pub struct RoadEmbeddings {
+    pub road_id: Vec<Id>,
+    pub dimension: Vec<u32>,
+    pub value: Vec<f32>,
+}
+
+impl RoadEmbeddings {
+    /// Appends the vector of the road `id`.
+    pub fn push(&mut self, id: Id, vector: &[f32]) {
+        for (dimension, value) in vector.iter().enumerate() {
+            self.road_id.push(id);
+            self.dimension.push(dimension as u32);
+            self.value.push(*value);
+        }
+    }
+
+    /// Number of dimensions of the vectors
+    pub fn dimensions(&self) -> usize {
+        self.dimension.iter().max().map_or(0, |d| *d as usize + 1)
+    }
+
+    /// The vector of every road by id, where missing values are 0.
+    pub fn vectors(&self) -> BTreeMap<Id, Vec<f32>> {
+        let dimensions = self.dimensions();
+        let mut vectors = BTreeMap::new();
+        for ((id, dimension), value) in self.road_id.iter().zip(&self.dimension).zip(&self.value) {
+            let vector = vectors.entry(*id).or_insert_with(|| vec![0.; dimensions]);
+            vector[*dimension as usize] = *value;
+        }
+        vectors
+    }
+}
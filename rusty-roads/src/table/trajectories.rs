@@ -1,7 +1,9 @@
+use comms::Parquet;
 use geo_types::LineString;
 
 use crate::Id;
 
+#[derive(Debug, Default, Clone, Parquet)]
 pub struct Trajectories {
     pub id: Vec<Id>,
     pub geom: Vec<LineString<f64>>,
@@ -0,0 +1,4 @@
+mod tile_id;
+pub use tile_id::*;
+mod tiling;
+pub use tiling::*;
@@ -0,0 +1,145 @@
+use std::f64::consts::PI;
+
+use geo_types::{coord, Point, Rect};
+
+/// Largest latitude representable in web mercator
+pub const MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+
+/// Identifies a web mercator tile at zoom level `z`.
+///
+/// Tiles form a hierarchical grid, where every tile is split into four tiles at the next zoom level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TileId {
+    pub z: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl TileId {
+    /// Deepest zoom level for which tile coordinates fit in a [`u32`]
+    pub const MAX_ZOOM: u8 = 31;
+
+    /// Creates a tile id, returning [`None`] if `x` or `y` is outside the grid at zoom level `z`.
+    pub fn new(z: u8, x: u32, y: u32) -> Option<TileId> {
+        let size = tiles_per_axis(z)?;
+        (f64::from(x) < size && f64::from(y) < size).then_some(TileId { z, x, y })
+    }
+
+    /// The tile at zoom level `z` containing `point`, given as longitude and latitude.
+    ///
+    /// Latitudes beyond [`MAX_LATITUDE`] are clamped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `z` is greater than [`TileId::MAX_ZOOM`].
+    pub fn containing(point: Point, z: u8) -> TileId {
+        let size = tiles_per_axis(z).expect("zoom level should be at most `TileId::MAX_ZOOM`");
+        let (x, y) = mercator(point);
+        let to_index = |v: f64| (v * size).floor().clamp(0., size - 1.) as u32;
+        TileId {
+            z,
+            x: to_index(x),
+            y: to_index(y),
+        }
+    }
+
+    /// The area covered by the tile in longitude and latitude.
+    pub fn bounds(&self) -> Rect {
+        Rect::new(
+            coord! {x: self.west(), y: self.south()},
+            coord! {x: self.east(), y: self.north()},
+        )
+    }
+
+    /// The tile containing this tile at the previous zoom level.
+    pub fn parent(&self) -> Option<TileId> {
+        let z = self.z.checked_sub(1)?;
+        Some(TileId {
+            z,
+            x: self.x / 2,
+            y: self.y / 2,
+        })
+    }
+
+    /// The four tiles covering this tile at the next zoom level.
+    pub fn children(&self) -> Option<[TileId; 4]> {
+        let z = self.z.checked_add(1).filter(|z| *z <= Self::MAX_ZOOM)?;
+        let (x, y) = (self.x * 2, self.y * 2);
+        Some([(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)].map(|(x, y)| TileId { z, x, y }))
+    }
+
+    pub(crate) fn west(&self) -> f64 {
+        longitude(self.x, self.z)
+    }
+
+    pub(crate) fn east(&self) -> f64 {
+        longitude(self.x + 1, self.z)
+    }
+
+    pub(crate) fn north(&self) -> f64 {
+        latitude(self.y, self.z)
+    }
+
+    pub(crate) fn south(&self) -> f64 {
+        latitude(self.y + 1, self.z)
+    }
+}
+
+fn tiles_per_axis(z: u8) -> Option<f64> {
+    (z <= TileId::MAX_ZOOM).then(|| f64::from(1u32 << z))
+}
+
+/// Projects longitude and latitude onto the unit square, with the origin in the north-west corner.
+fn mercator(point: Point) -> (f64, f64) {
+    let lat = point.y().clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+    let x = (point.x() + 180.) / 360.;
+    let y = (1. - lat.tan().asinh() / PI) / 2.;
+    (x, y)
+}
+
+/// Longitude of the western edge of tile column `x`.
+pub(crate) fn longitude(x: u32, z: u8) -> f64 {
+    f64::from(x) / f64::from(1u32 << z) * 360. - 180.
+}
+
+/// Latitude of the northern edge of tile row `y`.
+pub(crate) fn latitude(y: u32, z: u8) -> f64 {
+    let n = PI * (1. - 2. * f64::from(y) / f64::from(1u32 << z));
+    n.sinh().atan().to_degrees()
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::Contains;
+    use geo_types::point;
+
+    use super::*;
+
+    #[test]
+    fn containing_known_tile() {
+        // Aalborg at zoom 12
+        let tile = TileId::containing(point! {x: 9.9217, y: 57.0488}, 12);
+        assert_eq!(
+            tile,
+            TileId {
+                z: 12,
+                x: 2160,
+                y: 1253
+            }
+        );
+        assert!(tile.bounds().contains(&point! {x: 9.9217, y: 57.0488}));
+    }
+
+    #[test]
+    fn hierarchy() {
+        let tile = TileId::containing(point! {x: 9.9217, y: 57.0488}, 12);
+        let parent = tile.parent().unwrap();
+        assert_eq!(
+            parent,
+            TileId::containing(point! {x: 9.9217, y: 57.0488}, 11)
+        );
+        assert!(parent.children().unwrap().contains(&tile));
+        assert_eq!(TileId::new(0, 0, 0).unwrap().parent(), None);
+        assert_eq!(TileId::new(1, 2, 0), None);
+    }
+}
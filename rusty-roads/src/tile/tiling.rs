@@ -0,0 +1,402 @@
+use std::collections::{BTreeMap, HashMap};
+
+use geo::{Euclidean, InterpolatableLine};
+use geo_types::{Coord, Line, LineString};
+use itertools::Itertools;
+
+use crate::{default, Anonymities, Id, Road, RoadIndex, Roads};
+
+use super::{latitude, longitude, TileId};
+
+/// How roads crossing tile borders are assigned to tiles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BorderPolicy {
+    /// Every road belongs to the single tile containing the midpoint of its geometry.
+    #[default]
+    Canonical,
+    /// Roads are split at tile borders, and every tile gets the parts of the road inside it.
+    ///
+    /// A road may then appear in several tiles, and more than once in the same tile if it leaves and reenters it.
+    Clip,
+}
+
+/// Partitions road data into web mercator tiles at a fixed zoom level.
+///
+/// # Example
+/// ```
+/// use rusty_roads::{BorderPolicy, Tiling, RoadIndex};
+/// use geo::wkt;
+///
+/// let index = RoadIndex::from_ids_and_roads(&[0], &[wkt! {LINESTRING(-1.0 1.0, 1.0 1.0)}]);
+///
+/// let canonical = Tiling::new(1, BorderPolicy::Canonical).partition_index(&index);
+/// assert_eq!(canonical.len(), 1);
+///
+/// let clipped = Tiling::new(1, BorderPolicy::Clip).partition_index(&index);
+/// assert_eq!(clipped.len(), 2);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tiling {
+    pub zoom: u8,
+    pub policy: BorderPolicy,
+}
+
+impl Tiling {
+    /// # Panics
+    ///
+    /// Panics if `zoom` is greater than [`TileId::MAX_ZOOM`].
+    pub fn new(zoom: u8, policy: BorderPolicy) -> Tiling {
+        assert!(
+            zoom <= TileId::MAX_ZOOM,
+            "zoom level should be at most `TileId::MAX_ZOOM`"
+        );
+        Self { zoom, policy }
+    }
+
+    /// Assigns a road geometry to tiles according to the [`BorderPolicy`].
+    ///
+    /// Empty geometries are not assigned to any tile.
+    pub fn split(&self, geom: &LineString<f64>) -> Vec<(TileId, LineString<f64>)> {
+        match self.policy {
+            BorderPolicy::Canonical => geom
+                .point_at_ratio_from_start(&Euclidean, 0.5)
+                .map(|mid| (TileId::containing(mid, self.zoom), geom.clone()))
+                .into_iter()
+                .collect(),
+            BorderPolicy::Clip => self.clip(geom),
+        }
+    }
+
+    /// Partitions a [`Roads`] table by tile. Road ids are kept as is.
+    ///
+    /// The roads of a tile are not a [`Roads`] table, as a clipped road leaving and reentering a tile
+    /// has a row for each of its parts in the tile.
+    pub fn partition_roads(&self, roads: &Roads) -> BTreeMap<TileId, Vec<Road>> {
+        let mut tiles: BTreeMap<TileId, Vec<Road>> = default();
+        for road in roads.rows() {
+            for (tile, geom) in self.split(&road.geom) {
+                tiles.entry(tile).or_default().push(Road {
+                    geom,
+                    ..road.clone()
+                });
+            }
+        }
+        tiles
+    }
+
+    /// Partitions a [`RoadIndex`] by tile.
+    pub fn partition_index(&self, index: &RoadIndex) -> BTreeMap<TileId, RoadIndex> {
+        let mut tiles: BTreeMap<TileId, (Vec<Id>, Vec<LineString<f64>>)> = default();
+        for road in index.index.iter() {
+            for (tile, geom) in self.split(road.geom()) {
+                let (ids, geoms) = tiles.entry(tile).or_default();
+                ids.push(road.data);
+                geoms.push(geom);
+            }
+        }
+        tiles
+            .into_iter()
+            .map(|(tile, (ids, geoms))| (tile, RoadIndex::from_ids_and_roads(&ids, &geoms)))
+            .collect()
+    }
+
+    /// Partitions [`Anonymities`] by the tiles of the roads they belong to, as given by `roads`.
+    ///
+    /// Rows for roads not found in `roads` are left out.
+    pub fn partition_anonymities(
+        &self,
+        anonymities: &Anonymities,
+        roads: &Roads,
+    ) -> BTreeMap<TileId, Anonymities> {
+        let road_tiles: HashMap<Id, Vec<TileId>> = roads
+            .id()
+            .iter()
+            .zip(roads.geom.iter())
+            .map(|(id, geom)| {
+                let tiles = self.split(geom).into_iter().map(|(tile, _)| tile);
+                (*id, tiles.unique().collect())
+            })
+            .collect();
+
+        let mut tiles: BTreeMap<TileId, Anonymities> = default();
+        for (road_id, k) in anonymities.road_id.iter().zip(anonymities.current_k.iter()) {
+            for tile in road_tiles.get(road_id).into_iter().flatten() {
+                let anonymities = tiles.entry(*tile).or_default();
+                anonymities.road_id.push(*road_id);
+                anonymities.current_k.push(*k);
+            }
+        }
+        tiles
+    }
+
+    fn clip(&self, geom: &LineString<f64>) -> Vec<(TileId, LineString<f64>)> {
+        let z = self.zoom;
+        if let [only] = geom.0.as_slice() {
+            return vec![(TileId::containing((*only).into(), z), geom.clone())];
+        }
+
+        let mut pieces: Vec<(TileId, Vec<Coord>)> = vec![];
+        for line in geom.lines() {
+            let from = TileId::containing(line.start_point(), z);
+            let to = TileId::containing(line.end_point(), z);
+            let delta = line.delta();
+
+            let crossings_x = (from.x.min(to.x) + 1..=from.x.max(to.x))
+                .map(|x| (longitude(x, z) - line.start.x) / delta.x);
+            let crossings_y = (from.y.min(to.y) + 1..=from.y.max(to.y))
+                .map(|y| (latitude(y, z) - line.start.y) / delta.y);
+            let cuts = [0., 1.]
+                .into_iter()
+                .chain(
+                    crossings_x
+                        .chain(crossings_y)
+                        .filter(|t| (0.0..1.0).contains(t)),
+                )
+                .sorted_by(f64::total_cmp)
+                .dedup();
+
+            for (a, b) in cuts.tuple_windows() {
+                let (start, end) = (along(&line, a), along(&line, b));
+                let tile = TileId::containing(((start + end) / 2.).into(), z);
+                match pieces.last_mut() {
+                    Some((last, coords)) if *last == tile => coords.push(end),
+                    _ => pieces.push((tile, vec![start, end])),
+                }
+            }
+        }
+
+        pieces
+            .into_iter()
+            .map(|(tile, coords)| (tile, LineString::new(coords)))
+            .collect()
+    }
+}
+
+/// Merges tiles of roads back into one [`Roads`] table, ordered by id.
+///
+/// Roads found in several tiles are only included once, and clipped parts of a road are joined again.
+/// The joined geometry keeps the vertices added where the road crossed a tile border.
+pub fn merge_roads<I>(tiles: I) -> Roads
+where
+    I: IntoIterator<Item = Vec<Road>>,
+{
+    let mut parts: BTreeMap<Id, (Road, Vec<LineString<f64>>)> = default();
+    for tile in tiles {
+        for road in tile {
+            let (_, geoms) = parts
+                .entry(road.id)
+                .or_insert_with(|| (road.clone(), vec![]));
+            geoms.push(road.geom);
+        }
+    }
+
+    let mut roads: Roads = default();
+    for (_, (road, geoms)) in parts {
+        roads.push(Road {
+            geom: stitch(geoms),
+            ..road
+        });
+    }
+    roads
+}
+
+/// Merges tiles of a [`RoadIndex`] back into one index, joining clipped parts of roads.
+pub fn merge_index<I>(tiles: I) -> RoadIndex
+where
+    I: IntoIterator<Item = RoadIndex>,
+{
+    let parts = tiles
+        .into_iter()
+        .flat_map(|tile| tile.index.into_iter())
+        .map(|road| (road.data, road.geom().clone()))
+        .into_group_map();
+
+    let (ids, geoms): (Vec<Id>, Vec<_>) = parts
+        .into_iter()
+        .sorted_by_key(|(id, _)| *id)
+        .map(|(id, geoms)| (id, stitch(geoms)))
+        .unzip();
+    RoadIndex::from_ids_and_roads(&ids, &geoms)
+}
+
+/// Merges tiles of [`Anonymities`] back into one table, ordered by road id.
+///
+/// If a road occurs in several tiles the first occurrence is kept.
+pub fn merge_anonymities<I>(tiles: I) -> Anonymities
+where
+    I: IntoIterator<Item = Anonymities>,
+{
+    let mut rows: BTreeMap<Id, f64> = default();
+    for tile in tiles {
+        for (road_id, k) in tile.road_id.into_iter().zip(tile.current_k) {
+            rows.entry(road_id).or_insert(k);
+        }
+    }
+    let (road_id, current_k) = rows.into_iter().unzip();
+    Anonymities { road_id, current_k }
+}
+
+/// Point at `fraction` along `line`, which is exactly the start or end of `line` at 0 and 1.
+fn along(line: &Line<f64>, fraction: f64) -> Coord {
+    match fraction {
+        0. => line.start,
+        1. => line.end,
+        t => line.start + line.delta() * t,
+    }
+}
+
+/// Joins the parts of a clipped road by chaining parts where one ends and the next begins.
+fn stitch(parts: Vec<LineString<f64>>) -> LineString<f64> {
+    let mut parts = parts.into_iter().fold(vec![], |mut unique, part| {
+        if !unique.contains(&part) {
+            unique.push(part);
+        }
+        unique
+    });
+
+    let continues_other = |i: usize, parts: &[LineString<f64>]| {
+        parts
+            .iter()
+            .enumerate()
+            .any(|(j, other)| i != j && other.0.last() == parts[i].0.first())
+    };
+    let first = (0..parts.len())
+        .find(|i| !continues_other(*i, &parts))
+        .unwrap_or(0);
+    if parts.is_empty() {
+        return LineString::new(vec![]);
+    }
+
+    let mut coords = parts.remove(first).0;
+    while let Some(next) = parts.iter().position(|p| p.0.first() == coords.last()) {
+        coords.extend(parts.remove(next).0.into_iter().skip(1));
+    }
+    // parts that do not connect are appended in the order they were found
+    for part in parts {
+        coords.extend(part.0);
+    }
+    LineString::new(coords)
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::{point, wkt, Length};
+
+    use super::*;
+    use crate::{Direction, Insertable};
+
+    fn road(osm_id: u64, geom: LineString<f64>) -> Road {
+        Road {
+            id: 0,
+            geom,
+            osm_id,
+            code: 5111,
+            direction: Direction::Bidirectional,
+            maxspeed: 50,
+            layer: 0,
+            bridge: false,
+            tunnel: false,
+        }
+    }
+
+    /// Roads around Aalborg, the second crossing several tiles at zoom 14
+    fn roads() -> Roads {
+        let mut roads: Roads = default();
+        roads.insert(road(1, wkt! {LINESTRING(9.921 57.048, 9.922 57.049)}));
+        roads.insert(road(
+            2,
+            wkt! {LINESTRING(9.90 57.04, 9.95 57.04, 9.95 57.06, 9.90 57.06)},
+        ));
+        roads.insert(road(3, wkt! {LINESTRING(9.96 57.05)}));
+        roads
+    }
+
+    #[test]
+    fn canonical_assigns_each_road_once() {
+        let roads = roads();
+        let tiles = Tiling::new(14, BorderPolicy::Canonical).partition_roads(&roads);
+        assert_eq!(tiles.values().map(|t| t.len()).sum::<usize>(), 3);
+
+        let merged = merge_roads(tiles.into_values());
+        assert_eq!(merged.id(), roads.id());
+        assert_eq!(merged.geom, roads.geom);
+        assert_eq!(merged.osm_id(), roads.osm_id());
+    }
+
+    #[test]
+    fn clip_splits_at_borders_and_merges_back() {
+        let roads = roads();
+        let tiling = Tiling::new(14, BorderPolicy::Clip);
+        let tiles = tiling.partition_roads(&roads);
+        assert!(tiles.len() > 3);
+        for (tile, roads) in &tiles {
+            let bounds = tile.bounds();
+            let inside = |c: &Coord| {
+                (bounds.min().x - 1e-9..=bounds.max().x + 1e-9).contains(&c.x)
+                    && (bounds.min().y - 1e-9..=bounds.max().y + 1e-9).contains(&c.y)
+            };
+            assert!(roads.iter().flat_map(|r| r.geom.0.iter()).all(inside));
+        }
+
+        let merged = merge_roads(tiles.into_values());
+        assert_eq!(merged.id(), roads.id());
+        for (original, merged) in roads.geom.iter().zip(merged.geom.iter()) {
+            assert_eq!(original.0.first(), merged.0.first());
+            assert_eq!(original.0.last(), merged.0.last());
+            assert!((Euclidean.length(original) - Euclidean.length(merged)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn clip_keeps_every_part_in_a_tile() {
+        let tile = TileId::containing(point! {x: 9.92, y: 57.05}, 14);
+        let bounds = tile.bounds();
+        let (border, y) = (bounds.max().x, bounds.center().y);
+        let mut roads: Roads = default();
+        // crosses the eastern border of the tile and comes back
+        roads.insert(road(
+            1,
+            LineString::from(vec![
+                (border - 0.001, y),
+                (border + 0.001, y),
+                (border + 0.001, y + 0.0005),
+                (border - 0.001, y + 0.0005),
+            ]),
+        ));
+
+        let tiles = Tiling::new(14, BorderPolicy::Clip).partition_roads(&roads);
+        assert_eq!(tiles[&tile].len(), 2);
+        assert!(tiles[&tile].iter().all(|r| r.id == 0));
+
+        let merged = merge_roads(tiles.into_values());
+        assert_eq!(merged.len(), 1);
+        assert!(
+            (Euclidean.length(&merged.geom[0]) - Euclidean.length(&roads.geom[0])).abs() < 1e-9
+        );
+    }
+
+    #[test]
+    fn index_round_trip() {
+        let roads = roads();
+        let index = RoadIndex::from_ids_and_roads(roads.id(), &roads.geom);
+        let tiles = Tiling::new(14, BorderPolicy::Clip).partition_index(&index);
+        let merged = merge_index(tiles.into_values());
+        assert_eq!(merged.index.size(), index.index.size());
+    }
+
+    #[test]
+    fn anonymities_follow_roads() {
+        let roads = roads();
+        let anonymities = Anonymities {
+            road_id: vec![0, 1, 2, 42],
+            current_k: vec![1., 2., 3., 4.],
+        };
+        let tiling = Tiling::new(14, BorderPolicy::Clip);
+        let tiles = tiling.partition_anonymities(&anonymities, &roads);
+        assert!(tiles.values().all(|a| a.road_id.iter().all_unique()));
+
+        let merged = merge_anonymities(tiles.into_values());
+        assert_eq!(merged.road_id, vec![0, 1, 2]);
+        assert_eq!(merged.current_k, vec![1., 2., 3.]);
+    }
+}
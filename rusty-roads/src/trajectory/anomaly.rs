@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+use geo::{Haversine, Length};
+use petgraph::matrix_graph::IndexType;
+
+use crate::{Anonymities, Id, NodeId, NonNegativef64, RoadNetwork, RoadWithNode};
+
+/// How often each road has been travelled by earlier trajectories.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RoadUsage {
+    visits: HashMap<Id, f64>,
+}
+
+impl RoadUsage {
+    /// Counts a visit to every road of `route`.
+    pub fn add_route(&mut self, route: &[Id]) {
+        for id in route {
+            *self.visits.entry(*id).or_default() += 1.;
+        }
+    }
+
+    /// Number of visits to the road `id`, which may be fractional if visits are uncertain
+    pub fn visits(&self, id: Id) -> f64 {
+        self.visits.get(&id).copied().unwrap_or(0.)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.visits.is_empty()
+    }
+}
+
+impl From<&Anonymities> for RoadUsage {
+    /// The visits of every road, as stored in `current_k`.
+    fn from(anonymities: &Anonymities) -> Self {
+        let mut usage = Self::default();
+        for (id, visits) in anonymities.road_id.iter().zip(&anonymities.current_k) {
+            *usage.visits.entry(*id).or_default() += visits;
+        }
+        usage
+    }
+}
+
+/// Cost of a road whose length is not a number, e.g. because of invalid coordinates, so no shortest path takes it
+const IMPASSABLE: NonNegativef64 = match NonNegativef64::try_from(f64::INFINITY) {
+    Some(cost) => cost,
+    None => panic!("infinity is not negative"),
+};
+
+/// Parameters of [`score_anomaly`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnomalyParams {
+    /// Weight of the natural logarithm of the detour, see [`AnomalyScore::detour`]
+    pub detour_weight: f64,
+    /// Weight of the share of the route on unfamiliar roads, see [`AnomalyScore::unfamiliar_share`]
+    pub unfamiliar_weight: f64,
+    /// Weight of every jump between disconnected roads, see [`AnomalyScore::jumps`]
+    pub jump_weight: f64,
+    /// Roads visited fewer times than this are unfamiliar
+    pub min_visits: f64,
+    /// Trajectories scoring above this are anomalous
+    pub threshold: f64,
+}
+
+impl Default for AnomalyParams {
+    fn default() -> Self {
+        Self {
+            detour_weight: 1.0,
+            unfamiliar_weight: 1.0,
+            jump_weight: 1.0,
+            min_visits: 1.0,
+            threshold: 1.0,
+        }
+    }
+}
+
+/// How much a matched trajectory deviates from typical routes between its origin and destination.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnomalyScore {
+    /// Length of the route divided by the length of the shortest path between its ends,
+    /// which is infinite if the destination cannot be reached from the origin
+    pub detour: f64,
+    /// Fraction of the length of the route on roads visited fewer than [`AnomalyParams::min_visits`] times
+    pub unfamiliar_share: f64,
+    /// Number of consecutive roads that do not meet, where the trajectory teleported
+    pub jumps: usize,
+    /// Weighted sum of the logarithm of the detour, the unfamiliar share and the jumps
+    pub score: f64,
+    pub anomalous: bool,
+}
+
+/// Scores how anomalous the matched route `route` is, to catch bad GPS data and spoofed trajectories before they are stored.
+///
+/// The route is compared against the shortest path between its ends found by [`RoadNetwork::path_find`],
+/// and against the historical `usage` of its roads. An empty `usage` considers every road familiar.
+///
+/// Round trips are not scored by their detour, as they have no shortest path to compare against.
+///
+/// Returns [`None`] if the route is empty or has roads missing from `network`.
+pub fn score_anomaly<Idx: IndexType>(
+    route: &[Id],
+    network: &RoadNetwork<Idx>,
+    usage: &RoadUsage,
+    params: &AnomalyParams,
+) -> Option<AnomalyScore> {
+    let roads = route
+        .iter()
+        .map(|id| network.road(*id))
+        .collect::<Option<Vec<_>>>()?;
+    let (first, last) = (roads.first()?, roads.last()?);
+
+    let lengths: Vec<f64> = roads
+        .iter()
+        .map(|r| Haversine.length(&r.road.geom))
+        .collect();
+    let length: f64 = lengths.iter().sum();
+    let jumps = roads
+        .windows(2)
+        .filter(|w| shared(w[0], w[1]).is_none())
+        .count();
+
+    let detour = match roads.get(1) {
+        None => 1.,
+        Some(second) => {
+            let origin = away_from(first, second);
+            let destination = away_from(last, roads[roads.len() - 2]);
+            let shortest = network
+                .path_find(
+                    origin,
+                    destination,
+                    |road| {
+                        NonNegativef64::try_from(Haversine.length(&road.geom)).unwrap_or(IMPASSABLE)
+                    },
+                    |_| NonNegativef64::try_from(0.).expect("zero is not negative"),
+                )
+                .map(|(cost, _)| f64::from(cost))
+                .filter(|cost| cost.is_finite());
+            match shortest {
+                Some(shortest) if shortest > 0. => length / shortest,
+                // a round trip has no shortest path to compare against
+                Some(_) => 1.,
+                None => f64::INFINITY,
+            }
+        }
+    };
+
+    let unfamiliar_share = match usage.is_empty() || length <= 0. {
+        true => 0.,
+        false => {
+            let unfamiliar: f64 = route
+                .iter()
+                .zip(&lengths)
+                .filter(|(id, _)| usage.visits(**id) < params.min_visits)
+                .map(|(_, length)| length)
+                .sum();
+            unfamiliar / length
+        }
+    };
+
+    let score = params.detour_weight * detour.max(1.).ln()
+        + params.unfamiliar_weight * unfamiliar_share
+        + params.jump_weight * jumps as f64;
+    Some(AnomalyScore {
+        detour,
+        unfamiliar_share,
+        jumps,
+        score,
+        anomalous: score > params.threshold,
+    })
+}
+
+/// The node where `a` and `b` meet, if any
+fn shared(a: &RoadWithNode, b: &RoadWithNode) -> Option<NodeId> {
+    [a.source, a.target]
+        .into_iter()
+        .find(|n| *n == b.source || *n == b.target)
+}
+
+/// The node of `road` facing away from its neighbour `other` in a route
+fn away_from(road: &RoadWithNode, other: &RoadWithNode) -> NodeId {
+    match shared(road, other) {
+        Some(node) if node == road.source => road.target,
+        _ => road.source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::wkt;
+
+    use super::*;
+    use crate::{Direction, Road};
+
+    fn road(id: Id, geom: geo::LineString) -> Road {
+        Road {
+            id,
+            geom,
+            osm_id: id,
+            code: 5113,
+            direction: Direction::Bidirectional,
+            maxspeed: 50,
+            layer: 0,
+            bridge: false,
+            tunnel: false,
+        }
+    }
+
+    /// A direct road from node 1 to 2 and a detour around it through nodes 3 and 4, with a road from 0 to 1 and 2 to 5 at each end
+    fn roads() -> Vec<(Road, NodeId, NodeId)> {
+        vec![
+            (road(0, wkt! {LINESTRING(10.000 57.0, 10.002 57.0)}), 0, 1),
+            (road(1, wkt! {LINESTRING(10.002 57.0, 10.004 57.0)}), 1, 2),
+            (road(2, wkt! {LINESTRING(10.002 57.0, 10.002 57.004)}), 1, 3),
+            (
+                road(3, wkt! {LINESTRING(10.002 57.004, 10.004 57.004)}),
+                3,
+                4,
+            ),
+            (road(4, wkt! {LINESTRING(10.004 57.004, 10.004 57.0)}), 4, 2),
+            (road(5, wkt! {LINESTRING(10.004 57.0, 10.006 57.0)}), 2, 5),
+        ]
+    }
+
+    fn network(roads: &[(Road, NodeId, NodeId)]) -> RoadNetwork<'_, u16> {
+        RoadNetwork::new(roads.iter().map(|(road, source, target)| RoadWithNode {
+            road,
+            source: *source,
+            target: *target,
+        }))
+        .expect("test network should be valid")
+    }
+
+    #[test]
+    fn flags_detours() {
+        let roads = roads();
+        let network = network(&roads);
+        let params = AnomalyParams::default();
+        let usage = RoadUsage::default();
+
+        let direct = score_anomaly(&[0, 1, 5], &network, &usage, &params).expect("known roads");
+        assert!((direct.detour - 1.).abs() < 1e-9);
+        assert_eq!(direct.jumps, 0);
+        assert!(!direct.anomalous);
+
+        // driven backwards around the direct road
+        let detour =
+            score_anomaly(&[5, 4, 3, 2, 0], &network, &usage, &params).expect("known roads");
+        assert!(detour.detour > 2.);
+        assert!(detour.score > direct.score);
+
+        let teleport = score_anomaly(&[0, 3, 5], &network, &usage, &params).expect("known roads");
+        assert_eq!(teleport.jumps, 2);
+        assert!(teleport.anomalous);
+
+        assert_eq!(score_anomaly(&[0, 42], &network, &usage, &params), None);
+    }
+
+    #[test]
+    fn skips_roads_without_length() {
+        let mut roads = roads();
+        roads[1].0.geom.0[1].x = f64::NAN;
+        let network = network(&roads);
+
+        let around = score_anomaly(
+            &[0, 2, 3, 4, 5],
+            &network,
+            &RoadUsage::default(),
+            &AnomalyParams::default(),
+        )
+        .expect("known roads");
+        assert!((around.detour - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn flags_unfamiliar_roads() {
+        let roads = roads();
+        let network = network(&roads);
+        let params = AnomalyParams::default();
+        let mut usage = RoadUsage::default();
+        for _ in 0..10 {
+            usage.add_route(&[0, 1, 5]);
+        }
+
+        let usual = score_anomaly(&[0, 1, 5], &network, &usage, &params).expect("known roads");
+        assert_eq!(usual.unfamiliar_share, 0.);
+        assert!(!usual.anomalous);
+
+        let unusual =
+            score_anomaly(&[0, 2, 3, 4, 5], &network, &usage, &params).expect("known roads");
+        assert!(unusual.unfamiliar_share > 0.7);
+        assert!(unusual.anomalous);
+
+        let anonymities = Anonymities {
+            road_id: vec![2, 3, 4],
+            current_k: vec![5., 5., 5.],
+        };
+        let known = score_anomaly(
+            &[2, 3, 4],
+            &network,
+            &RoadUsage::from(&anonymities),
+            &params,
+        )
+        .expect("known roads");
+        assert_eq!(known.unfamiliar_share, 0.);
+    }
+}
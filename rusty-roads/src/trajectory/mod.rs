@@ -0,0 +1,8 @@
+mod timed;
+pub use timed::*;
+mod preprocess;
+pub use preprocess::*;
+mod mode;
+pub use mode::*;
+mod anomaly;
+pub use anomaly::*;
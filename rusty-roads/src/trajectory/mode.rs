@@ -0,0 +1,319 @@
+use geo::{Distance, Haversine, Length};
+use serde::{Deserialize, Serialize};
+
+use crate::{FeatureClass, FeatureClassKey, Id, Queryable, RoadKey, Roads};
+
+use super::TimedTrajectory;
+
+/// How a trajectory was travelled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum TransportMode {
+    Walk,
+    Bicycle,
+    Car,
+}
+
+impl TransportMode {
+    /// Every mode, in the order of [`TransportMode::index`]
+    pub const ALL: [TransportMode; 3] = [Self::Walk, Self::Bicycle, Self::Car];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// The mode that only uses roads of the feature class `fclass`, if any, e.g. walking for footways.
+    pub fn exclusive_to(fclass: &str) -> Option<TransportMode> {
+        match fclass {
+            "footway" | "path" | "pedestrian" | "steps" | "bridleway" => Some(Self::Walk),
+            "cycleway" => Some(Self::Bicycle),
+            "motorway" | "motorway_link" | "trunk" | "trunk_link" => Some(Self::Car),
+            _ => None,
+        }
+    }
+}
+
+/// Number of values in [`ModeFeatures::to_array`]
+pub const NUM_MODE_FEATURES: usize = 6;
+
+/// Speed, acceleration and road features of a trip, used to tell its [`TransportMode`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ModeFeatures {
+    /// Median speed in meters per second between consecutive points
+    pub median_speed: f64,
+    /// 85th percentile of the speed in meters per second between consecutive points, which ignores short bursts of noise
+    pub p85_speed: f64,
+    /// Mean absolute change in speed in meters per second squared
+    pub mean_acceleration: f64,
+    /// Fraction of the length of the matched roads exclusive to each mode, in the order of [`TransportMode::ALL`],
+    /// see [`TransportMode::exclusive_to`]
+    pub exclusive_share: [f64; 3],
+}
+
+impl ModeFeatures {
+    /// Computes the features of `trajectory`, which was matched to the roads `matched`.
+    ///
+    /// The feature class of a road is looked up in `classes` by its code. Roads missing from `roads` are ignored.
+    pub fn new(
+        trajectory: &TimedTrajectory,
+        matched: &[Id],
+        roads: &Roads,
+        classes: &FeatureClass,
+    ) -> Self {
+        let points = trajectory.points();
+        // speed and time of the middle of every step with a positive duration
+        let steps: Vec<(f64, f64)> = points
+            .windows(2)
+            .filter(|w| w[1].time > w[0].time)
+            .map(|w| {
+                let dt = w[1].time - w[0].time;
+                let speed = Haversine.distance(w[0].point, w[1].point) / dt;
+                (speed, w[0].time + dt / 2.)
+            })
+            .collect();
+        let accelerations: Vec<f64> = steps
+            .windows(2)
+            .map(|w| ((w[1].0 - w[0].0) / (w[1].1 - w[0].1)).abs())
+            .collect();
+
+        let mut speeds: Vec<f64> = steps.iter().map(|(speed, _)| *speed).collect();
+        speeds.sort_by(f64::total_cmp);
+
+        let mut exclusive = [0.; 3];
+        let mut total = 0.;
+        for i in matched
+            .iter()
+            .filter_map(|id| roads.find_index(&RoadKey(*id)))
+        {
+            let length = Haversine.length(&roads.geom[i]);
+            total += length;
+            let mode = classes
+                .find_index(&FeatureClassKey(roads.code[i]))
+                .and_then(|c| TransportMode::exclusive_to(&classes.fclass()[c]));
+            if let Some(mode) = mode {
+                exclusive[mode.index()] += length;
+            }
+        }
+        if total > 0. {
+            exclusive.iter_mut().for_each(|share| *share /= total);
+        }
+
+        Self {
+            median_speed: quantile(&speeds, 0.5),
+            p85_speed: quantile(&speeds, 0.85),
+            mean_acceleration: accelerations.iter().sum::<f64>()
+                / accelerations.len().max(1) as f64,
+            exclusive_share: exclusive,
+        }
+    }
+
+    /// The features as a flat array, as input to learned classifiers.
+    pub fn to_array(&self) -> [f64; NUM_MODE_FEATURES] {
+        let [walk, bicycle, car] = self.exclusive_share;
+        [
+            self.median_speed,
+            self.p85_speed,
+            self.mean_acceleration,
+            walk,
+            bicycle,
+            car,
+        ]
+    }
+}
+
+/// Nearest-rank quantile of sorted `values`, or 0 if there are none.
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    match sorted.len() {
+        0 => 0.,
+        n => sorted[((n - 1) as f64 * q).round() as usize],
+    }
+}
+
+/// The most likely [`TransportMode`] of a trip, and the probability of every mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModeClassification {
+    pub mode: TransportMode,
+    /// Probability of `mode`, between 0 and 1
+    pub confidence: f64,
+    /// Probability of every mode, in the order of [`TransportMode::ALL`]
+    pub probabilities: [f64; 3],
+}
+
+impl ModeClassification {
+    /// Classifies a trip as the most probable mode given unnormalized log probabilities in the order of [`TransportMode::ALL`].
+    pub fn from_scores(scores: [f64; 3]) -> Self {
+        let max = scores.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let exp = scores.map(|s| (s - max).exp());
+        let sum: f64 = exp.iter().sum();
+        let probabilities = exp.map(|e| e / sum);
+        let (mode, confidence) = TransportMode::ALL
+            .into_iter()
+            .zip(probabilities)
+            .max_by(|(_, fst), (_, snd)| fst.total_cmp(snd))
+            .unwrap_or((TransportMode::Car, 1.));
+        Self {
+            mode,
+            confidence,
+            probabilities,
+        }
+    }
+}
+
+/// Parameters of the rule-based baseline [`classify_mode`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModeRules {
+    /// Typical speed in meters per second of each mode, in the order of [`TransportMode::ALL`]
+    pub typical_speed: [f64; 3],
+    /// Standard deviation of the natural logarithm of the speed around the typical speed
+    pub speed_spread: f64,
+    /// Added per share of roads exclusive to a mode to its score, and subtracted from the scores of the other modes
+    pub road_weight: f64,
+    /// Mean acceleration in meters per second squared above which walking and cycling become unlikely
+    pub max_active_acceleration: f64,
+}
+
+impl Default for ModeRules {
+    fn default() -> Self {
+        Self {
+            typical_speed: [1.4, 4.5, 11.0],
+            speed_spread: 0.45,
+            road_weight: 4.0,
+            max_active_acceleration: 1.5,
+        }
+    }
+}
+
+/// Classifies a trip as walked, cycled or driven with hand-tuned rules.
+///
+/// Each mode is scored by how close the 85th percentile speed is to its typical speed,
+/// by the share of roads exclusive to it or to other modes, and, for walking and cycling,
+/// by how far the mean acceleration exceeds what people accelerate by themselves.
+/// The scores are turned into probabilities with a softmax.
+///
+/// # Example
+/// ```
+/// use rusty_roads::{classify_mode, ModeFeatures, ModeRules, TransportMode};
+///
+/// let features = ModeFeatures {
+///     p85_speed: 1.5,
+///     median_speed: 1.3,
+///     ..Default::default()
+/// };
+/// let classification = classify_mode(&features, &ModeRules::default());
+/// assert_eq!(classification.mode, TransportMode::Walk);
+/// assert!(classification.confidence > 0.9);
+/// ```
+pub fn classify_mode(features: &ModeFeatures, rules: &ModeRules) -> ModeClassification {
+    // a stationary trip says nothing about speed, but cannot be compared on a log scale
+    let speed = features.p85_speed.max(0.1).ln();
+    let excess_acceleration = (features.mean_acceleration - rules.max_active_acceleration).max(0.)
+        / rules.max_active_acceleration;
+    let scores = TransportMode::ALL.map(|mode| {
+        let z = (speed - rules.typical_speed[mode.index()].ln()) / rules.speed_spread;
+        let roads: f64 = TransportMode::ALL
+            .iter()
+            .map(|other| match *other == mode {
+                true => features.exclusive_share[other.index()],
+                false => -features.exclusive_share[other.index()],
+            })
+            .sum();
+        let acceleration = match mode {
+            TransportMode::Car => 0.,
+            _ => excess_acceleration,
+        };
+        -0.5 * z * z + rules.road_weight * roads - acceleration
+    });
+    ModeClassification::from_scores(scores)
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::wkt;
+
+    use super::*;
+    use crate::{Direction, FeatureClassRow, Insertable, Road};
+
+    fn tables() -> (Roads, FeatureClass) {
+        let mut classes = FeatureClass::default();
+        classes.insert_many([
+            FeatureClassRow {
+                code: 5111,
+                fclass: "motorway".into(),
+            },
+            FeatureClassRow {
+                code: 5141,
+                fclass: "footway".into(),
+            },
+            FeatureClassRow {
+                code: 5113,
+                fclass: "primary".into(),
+            },
+        ]);
+        let road = |id, code, geom| Road {
+            id,
+            geom,
+            osm_id: id,
+            code,
+            direction: Direction::Bidirectional,
+            maxspeed: 0,
+            layer: 0,
+            bridge: false,
+            tunnel: false,
+        };
+        let roads = [
+            road(0, 5111, wkt! {LINESTRING(10.0 57.0, 10.1 57.0)}),
+            road(1, 5141, wkt! {LINESTRING(10.0 57.001, 10.1 57.001)}),
+            road(2, 5113, wkt! {LINESTRING(10.0 57.002, 10.1 57.002)}),
+        ]
+        .into_iter()
+        .collect();
+        (roads, classes)
+    }
+
+    /// Points every 10 seconds along latitude 57 at the given speeds in meters per second
+    fn trajectory(speeds: &[f64]) -> TimedTrajectory {
+        // one degree of longitude at latitude 57 is about 60.6 km
+        let degrees_per_meter = 1. / (111_195. * 57f64.to_radians().cos());
+        let mut lon = 10.0;
+        let mut coords = vec![(lon, 57.0)];
+        for speed in speeds {
+            lon += speed * 10. * degrees_per_meter;
+            coords.push((lon, 57.0));
+        }
+        let times: Vec<_> = (0..coords.len()).map(|i| i as f64 * 10.).collect();
+        TimedTrajectory::from_times(&coords.into(), &times).expect("times are ordered")
+    }
+
+    #[test]
+    fn computes_features() {
+        let (roads, classes) = tables();
+        let features = ModeFeatures::new(&trajectory(&[1., 2., 3.]), &[0, 1, 2], &roads, &classes);
+        assert!((features.median_speed - 2.).abs() < 0.01);
+        assert!((features.mean_acceleration - 0.1).abs() < 0.01);
+        let [walk, bicycle, car] = features.exclusive_share;
+        assert!((walk - 1. / 3.).abs() < 0.01 && (car - 1. / 3.).abs() < 0.01);
+        assert_eq!(bicycle, 0.);
+    }
+
+    #[test]
+    fn tells_walking_from_driving() {
+        let (roads, classes) = tables();
+        let rules = ModeRules::default();
+
+        let walk = ModeFeatures::new(&trajectory(&[1.3, 1.4, 1.2, 1.5]), &[1], &roads, &classes);
+        assert_eq!(classify_mode(&walk, &rules).mode, TransportMode::Walk);
+
+        let cycle = ModeFeatures::new(&trajectory(&[4., 5., 4.5, 5.]), &[2], &roads, &classes);
+        assert_eq!(classify_mode(&cycle, &rules).mode, TransportMode::Bicycle);
+
+        let drive = ModeFeatures::new(&trajectory(&[20., 25., 30., 28.]), &[0], &roads, &classes);
+        let driving = classify_mode(&drive, &rules);
+        assert_eq!(driving.mode, TransportMode::Car);
+        assert!(driving.confidence > 0.99);
+
+        // slow traffic on a motorway is still driving, but less certainly
+        let jam = ModeFeatures::new(&trajectory(&[3., 4., 3.5, 4.]), &[0], &roads, &classes);
+        let classification = classify_mode(&jam, &rules);
+        assert_eq!(classification.mode, TransportMode::Car);
+        assert!(classification.confidence < driving.confidence);
+    }
+}
@@ -0,0 +1,321 @@
+use std::ops::Range;
+
+use geo::{Distance, Haversine};
+use geo_types::Point;
+
+use crate::{Meter, Timestamp};
+
+use super::{TimedPoint, TimedTrajectory};
+
+/// Mean radius of the earth in meters, as used by [`Haversine`]
+const EARTH_RADIUS: Meter = 6_371_008.8;
+
+/// Thresholds used by [`preprocess`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PreprocessConfig {
+    /// Fastest plausible speed in meters per second, see [`remove_outliers`]
+    pub max_speed: f64,
+    /// Largest plausible change in speed in meters per second squared, see [`remove_outliers`]
+    pub max_acceleration: f64,
+    /// Points closer than this many meters to the previous point are considered stationary, see [`dedup_stationary`]
+    pub stationary_distance: Meter,
+    /// Trips are split where no point was observed for this many seconds, see [`split_trips`]
+    pub max_gap: f64,
+    /// Tolerance in meters of the simplification, see [`simplify`]
+    pub simplify_tolerance: Meter,
+}
+
+impl Default for PreprocessConfig {
+    fn default() -> Self {
+        Self {
+            max_speed: 70.0,
+            max_acceleration: 10.0,
+            stationary_distance: 5.0,
+            max_gap: 300.0,
+            simplify_tolerance: 5.0,
+        }
+    }
+}
+
+/// Cleans a raw trajectory before matching: removes outliers, deduplicates stationary points,
+/// splits it into trips at long gaps, and simplifies every trip.
+///
+/// # Example
+/// ```
+/// use geo::wkt;
+/// use rusty_roads::{preprocess, PreprocessConfig, TimedTrajectory};
+///
+/// let geom = wkt! {LINESTRING(10.0 57.0, 10.001 57.0, 10.5 57.5, 10.002 57.0, 10.003 57.0, 10.004 57.0)};
+/// let raw = TimedTrajectory::from_times(&geom, &[0., 10., 20., 30., 1000., 1010.]).unwrap();
+///
+/// // the jump to 10.5 57.5 is an outlier, and the trip is split by the gap of 970 seconds
+/// let trips = preprocess(&raw, &PreprocessConfig::default());
+/// assert_eq!(trips.len(), 2);
+/// assert_eq!(trips[0].line_string(), wkt! {LINESTRING(10.0 57.0, 10.002 57.0)});
+/// ```
+pub fn preprocess(trajectory: &TimedTrajectory, config: &PreprocessConfig) -> Vec<TimedTrajectory> {
+    let cleaned = remove_outliers(trajectory, config.max_speed, config.max_acceleration);
+    let cleaned = dedup_stationary(&cleaned, config.stationary_distance);
+    split_trips(&cleaned, config.max_gap)
+        .iter()
+        .map(|trip| simplify(trip, config.simplify_tolerance))
+        .collect()
+}
+
+/// Removes points that could only be reached from the previous point by driving faster than `max_speed` meters per second,
+/// or by changing speed by more than `max_acceleration` meters per second squared.
+///
+/// The first point is always kept, so a trajectory starting with an outlier is best reversed and cleaned again.
+pub fn remove_outliers(
+    trajectory: &TimedTrajectory,
+    max_speed: f64,
+    max_acceleration: f64,
+) -> TimedTrajectory {
+    let mut kept: Vec<TimedPoint> = Vec::with_capacity(trajectory.len());
+    // speed between the last two kept points
+    let mut last_speed = None;
+    for point in trajectory {
+        let Some(prev) = kept.last() else {
+            kept.push(*point);
+            continue;
+        };
+        let elapsed = point.time - prev.time;
+        let distance = Haversine.distance(prev.point, point.point);
+        let speed = match elapsed > 0. {
+            true => distance / elapsed,
+            false if distance == 0. => 0.,
+            false => f64::INFINITY,
+        };
+        let accelerating = last_speed.is_some_and(|last: f64| {
+            elapsed > 0. && (speed - last).abs() / elapsed > max_acceleration
+        });
+        if speed <= max_speed && !accelerating {
+            kept.push(*point);
+            last_speed = Some(speed);
+        }
+    }
+    TimedTrajectory::from_ordered(kept)
+}
+
+/// Removes points within `min_distance` meters of the last point kept, keeping the first point of every stationary run.
+///
+/// The last point of the trajectory is always kept, so the trajectory still ends where and when it did.
+pub fn dedup_stationary(trajectory: &TimedTrajectory, min_distance: Meter) -> TimedTrajectory {
+    let points = trajectory.points();
+    let mut kept: Vec<TimedPoint> = Vec::with_capacity(points.len());
+    for (i, point) in points.iter().enumerate() {
+        let moved = kept
+            .last()
+            .is_none_or(|prev| Haversine.distance(prev.point, point.point) >= min_distance);
+        if moved || i == points.len() - 1 {
+            kept.push(*point);
+        }
+    }
+    TimedTrajectory::from_ordered(kept)
+}
+
+/// Splits the trajectory wherever more than `max_gap` seconds pass between two points.
+pub fn split_trips(trajectory: &TimedTrajectory, max_gap: f64) -> Vec<TimedTrajectory> {
+    trajectory
+        .points()
+        .chunk_by(|a, b| b.time - a.time <= max_gap)
+        .map(|trip| TimedTrajectory::from_ordered(trip.to_vec()))
+        .collect()
+}
+
+/// A place where the trajectory stayed for a while, e.g. to park or to wait at a traffic jam.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StayPoint {
+    /// Mean position of the points in the stay
+    pub point: Point,
+    pub arrival: Timestamp,
+    pub departure: Timestamp,
+    /// Indices of the points of the trajectory in the stay
+    pub points: Range<usize>,
+}
+
+/// Finds the places where the trajectory stayed within `max_radius` meters for at least `min_duration` seconds,
+/// following Li et al. (2008).
+///
+/// Stays do not overlap, and are ordered by time.
+pub fn stay_points(
+    trajectory: &TimedTrajectory,
+    max_radius: Meter,
+    min_duration: f64,
+) -> Vec<StayPoint> {
+    let points = trajectory.points();
+    let mut stays = vec![];
+    let mut i = 0;
+    while i < points.len() {
+        let anchor = points[i];
+        let end = points[i..]
+            .iter()
+            .position(|p| Haversine.distance(anchor.point, p.point) > max_radius)
+            .map_or(points.len(), |offset| i + offset);
+        let last = points[end - 1];
+        if last.time - anchor.time >= min_duration {
+            let count = (end - i) as f64;
+            let (x, y) = points[i..end]
+                .iter()
+                .fold((0., 0.), |(x, y), p| (x + p.point.x(), y + p.point.y()));
+            stays.push(StayPoint {
+                point: Point::new(x / count, y / count),
+                arrival: anchor.time,
+                departure: last.time,
+                points: i..end,
+            });
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+    stays
+}
+
+/// Simplifies the trajectory using the Douglas–Peucker algorithm,
+/// such that no removed point is more than `tolerance` meters from the simplified trajectory.
+///
+/// The kept points retain their timestamps. The first and last point are always kept.
+pub fn simplify(trajectory: &TimedTrajectory, tolerance: Meter) -> TimedTrajectory {
+    let points = trajectory.points();
+    if points.len() < 3 {
+        return trajectory.clone();
+    }
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+    let mut stack = vec![(0, points.len() - 1)];
+    while let Some((first, last)) = stack.pop() {
+        let (a, b) = (points[first].point, points[last].point);
+        let farthest = (first + 1..last)
+            .map(|i| (i, segment_distance(points[i].point, a, b)))
+            .max_by(|(_, fst), (_, snd)| fst.total_cmp(snd));
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance {
+                keep[i] = true;
+                stack.push((first, i));
+                stack.push((i, last));
+            }
+        }
+    }
+
+    TimedTrajectory::from_ordered(
+        points
+            .iter()
+            .zip(keep)
+            .filter_map(|(p, keep)| keep.then_some(*p))
+            .collect(),
+    )
+}
+
+/// Distance in meters from `p` to the segment from `a` to `b`, in an equirectangular projection around `a`.
+fn segment_distance(p: Point, a: Point, b: Point) -> Meter {
+    let scale = EARTH_RADIUS * std::f64::consts::PI / 180.;
+    let cos = a.y().to_radians().cos();
+    let local = |q: Point| ((q.x() - a.x()) * cos * scale, (q.y() - a.y()) * scale);
+    let (px, py) = local(p);
+    let (bx, by) = local(b);
+    let length = bx * bx + by * by;
+    let t = match length > 0. {
+        true => ((px * bx + py * by) / length).clamp(0., 1.),
+        false => 0.,
+    };
+    (px - t * bx).hypot(py - t * by)
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::wkt;
+
+    use super::*;
+
+    fn trajectory(geom: geo_types::LineString<f64>, times: &[Timestamp]) -> TimedTrajectory {
+        TimedTrajectory::from_times(&geom, times).expect("test times should be ordered")
+    }
+
+    #[test]
+    fn removes_speed_and_acceleration_spikes() {
+        // roughly 60 meters every 10 seconds, with a jump of 600 meters in the middle
+        let raw = trajectory(
+            wkt! {LINESTRING(10.000 57.0, 10.001 57.0, 10.011 57.0, 10.002 57.0, 10.003 57.0)},
+            &[0., 10., 20., 30., 40.],
+        );
+        let cleaned = remove_outliers(&raw, 30., 10.);
+        assert_eq!(
+            cleaned.line_string(),
+            wkt! {LINESTRING(10.000 57.0, 10.001 57.0, 10.002 57.0, 10.003 57.0)}
+        );
+
+        // 6 m/s, then 60 m/s within a second
+        let raw = trajectory(
+            wkt! {LINESTRING(10.000 57.0, 10.0001 57.0, 10.0011 57.0)},
+            &[0., 1., 2.],
+        );
+        assert_eq!(remove_outliers(&raw, 70., 10.).len(), 2);
+        assert_eq!(remove_outliers(&raw, 70., 100.).len(), 3);
+    }
+
+    #[test]
+    fn dedups_stationary_points() {
+        let raw = trajectory(
+            wkt! {LINESTRING(10.0 57.0, 10.00001 57.0, 10.0 57.00001, 10.001 57.0, 10.00101 57.0)},
+            &[0., 1., 2., 3., 4.],
+        );
+        let deduped = dedup_stationary(&raw, 5.);
+        assert_eq!(
+            deduped.points().iter().map(|p| p.time).collect::<Vec<_>>(),
+            vec![0., 3., 4.]
+        );
+    }
+
+    #[test]
+    fn finds_stay_points() {
+        // drives, waits about 10 minutes within a few meters, and drives on
+        let raw = trajectory(
+            wkt! {LINESTRING(
+                10.000 57.0,
+                10.001 57.0,
+                10.00101 57.00001,
+                10.00102 57.0,
+                10.00101 56.99999,
+                10.002 57.0
+            )},
+            &[0., 10., 200., 400., 610., 620.],
+        );
+        let stays = stay_points(&raw, 20., 300.);
+        assert_eq!(stays.len(), 1);
+        assert_eq!(stays[0].points, 1..5);
+        assert_eq!((stays[0].arrival, stays[0].departure), (10., 610.));
+        assert!(Haversine.distance(stays[0].point, Point::new(10.00101, 57.0)) < 1.);
+
+        assert!(stay_points(&raw, 20., 700.).is_empty());
+    }
+
+    #[test]
+    fn simplifies_in_meters() {
+        // the middle point is about 5.6 meters off the line between its neighbours
+        let raw = trajectory(
+            wkt! {LINESTRING(10.000 57.0, 10.001 57.00005, 10.002 57.0)},
+            &[0., 10., 20.],
+        );
+        assert_eq!(simplify(&raw, 10.).len(), 2);
+        assert_eq!(simplify(&raw, 5.).len(), 3);
+        assert_eq!(simplify(&raw, 10.).points()[1].time, 20.);
+    }
+
+    #[test]
+    fn splits_at_gaps() {
+        let raw = trajectory(
+            wkt! {LINESTRING(10.000 57.0, 10.001 57.0, 10.002 57.0, 10.003 57.0)},
+            &[0., 10., 500., 510.],
+        );
+        let trips = split_trips(&raw, 300.);
+        assert_eq!(
+            trips.iter().map(TimedTrajectory::len).collect::<Vec<_>>(),
+            vec![2, 2]
+        );
+        assert_eq!(trips[1].points()[0].time, 500.);
+        assert!(split_trips(&TimedTrajectory::default(), 300.).is_empty());
+    }
+}
@@ -0,0 +1,160 @@
+use geo_types::{LineString, Point};
+use thiserror::Error;
+
+use crate::{Meter, Timestamp};
+
+/// A position observed at a known time, along with what the receiver reported about it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimedPoint {
+    pub point: Point,
+    pub time: Timestamp,
+    /// Estimated accuracy of the position in meters
+    pub accuracy: Option<Meter>,
+    /// Speed in meters per second
+    pub speed: Option<f64>,
+}
+
+impl TimedPoint {
+    pub fn new(point: Point, time: Timestamp) -> TimedPoint {
+        Self {
+            point,
+            time,
+            accuracy: None,
+            speed: None,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TrajectoryError {
+    #[error("point {0} has no valid timestamp")]
+    InvalidTime(usize),
+    #[error("point {0} was observed before the point preceding it")]
+    Unordered(usize),
+    #[error("got {0} points but {1} timestamps")]
+    LengthMismatch(usize, usize),
+}
+
+/// A trajectory whose points are ordered by the time they were observed.
+///
+/// # Example
+/// ```
+/// use rusty_roads::{TimedTrajectory, TrajectoryError};
+/// use geo::wkt;
+///
+/// let geom = wkt! {LINESTRING(10.0 57.0, 10.001 57.0, 10.002 57.0)};
+/// let trajectory = TimedTrajectory::from_times(&geom, &[0., 5., 10.]).unwrap();
+/// assert_eq!(trajectory.duration(), 10.);
+/// assert_eq!(trajectory.line_string(), geom);
+///
+/// let unordered = TimedTrajectory::from_times(&geom, &[0., 10., 5.]);
+/// assert_eq!(unordered, Err(TrajectoryError::Unordered(2)));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TimedTrajectory {
+    points: Vec<TimedPoint>,
+}
+
+impl TimedTrajectory {
+    /// Creates a trajectory from points ordered by time.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a timestamp is not finite, or if a point was observed before the point preceding it.
+    pub fn new(points: Vec<TimedPoint>) -> Result<TimedTrajectory, TrajectoryError> {
+        if let Some(i) = points.iter().position(|p| !p.time.is_finite()) {
+            return Err(TrajectoryError::InvalidTime(i));
+        }
+        if let Some(i) = points.windows(2).position(|w| w[1].time < w[0].time) {
+            return Err(TrajectoryError::Unordered(i + 1));
+        }
+        Ok(Self { points })
+    }
+
+    /// Creates a trajectory from the points of `geom` observed at the corresponding `times`.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if there is not exactly one timestamp per point,
+    /// or for the same reasons as [`TimedTrajectory::new`].
+    pub fn from_times(
+        geom: &LineString<f64>,
+        times: &[Timestamp],
+    ) -> Result<TimedTrajectory, TrajectoryError> {
+        if geom.0.len() != times.len() {
+            return Err(TrajectoryError::LengthMismatch(geom.0.len(), times.len()));
+        }
+        Self::new(
+            geom.points()
+                .zip(times)
+                .map(|(point, time)| TimedPoint::new(point, *time))
+                .collect(),
+        )
+    }
+
+    /// Creates a trajectory from points known to be ordered by time, such as a subsequence of another trajectory.
+    pub(crate) fn from_ordered(points: Vec<TimedPoint>) -> TimedTrajectory {
+        debug_assert!(points.windows(2).all(|w| w[0].time <= w[1].time));
+        Self { points }
+    }
+
+    pub fn points(&self) -> &[TimedPoint] {
+        &self.points
+    }
+
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// The geometry of the trajectory, without timestamps
+    pub fn line_string(&self) -> LineString<f64> {
+        self.points.iter().map(|p| p.point).collect()
+    }
+
+    /// Seconds between the first and the last point
+    pub fn duration(&self) -> f64 {
+        match (self.points.first(), self.points.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.,
+        }
+    }
+
+    /// Points and the times they were observed, as accepted by [`TrajectoryIndex::insert`](crate::TrajectoryIndex::insert)
+    pub fn timestamped(&self) -> impl Iterator<Item = (Point, Timestamp)> + '_ {
+        self.points.iter().map(|p| (p.point, p.time))
+    }
+}
+
+impl<'a> IntoIterator for &'a TimedTrajectory {
+    type Item = &'a TimedPoint;
+    type IntoIter = std::slice::Iter<'a, TimedPoint>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.points.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use geo::wkt;
+
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_times() {
+        let geom = wkt! {LINESTRING(10.0 57.0, 10.001 57.0)};
+        assert_eq!(
+            TimedTrajectory::from_times(&geom, &[0., f64::NAN]),
+            Err(TrajectoryError::InvalidTime(1))
+        );
+        assert_eq!(
+            TimedTrajectory::from_times(&geom, &[0.]),
+            Err(TrajectoryError::LengthMismatch(2, 1))
+        );
+        assert!(TimedTrajectory::from_times(&geom, &[3., 3.]).is_ok());
+    }
+}
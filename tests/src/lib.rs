@@ -30,6 +30,9 @@ mod tests {
         ($v: expr, $v2: expr, $e:ident) => {
             assert!($v.$e.iter().zip($v2.$e.iter()).all(eq))
         };
+        ($v: expr, $v2: expr, $e:ident()) => {
+            assert!($v.$e().iter().zip($v2.$e().iter()).all(eq))
+        };
     }
 
     #[test]
@@ -38,8 +41,8 @@ mod tests {
         let check = roads.clone();
         let parquet = roads.to_parquet().unwrap();
         let deque = Roads::from_parquet(parquet).unwrap();
-        check!(check, deque, id);
-        check!(check, deque, osm_id);
+        check!(check, deque, id());
+        check!(check, deque, osm_id());
         check!(check, deque, geom);
         check!(check, deque, code);
         check!(check, deque, direction);
@@ -48,4 +51,141 @@ mod tests {
         check!(check, deque, bridge);
         check!(check, deque, tunnel);
     }
+
+    #[test]
+    fn test_road_embeddings_parquet() {
+        let mut embeddings = RoadEmbeddings::default();
+        for id in 0..1000 {
+            embeddings.push(id, &(0..16).map(|_| random()).collect::<Vec<f32>>());
+        }
+        let check = embeddings.clone();
+        let parquet = embeddings.to_parquet().unwrap();
+        let deque = RoadEmbeddings::from_parquet(parquet).unwrap();
+        check!(check, deque, road_id);
+        check!(check, deque, dimension);
+        check!(check, deque, value);
+        assert_eq!(deque.dimensions(), 16);
+    }
+
+    #[test]
+    fn test_roads_indexes_after_parquet() {
+        let roads: Roads = ((0..1000).map(random_road)).collect();
+        let check = roads.clone();
+        let mut deque = Roads::from_parquet(roads.to_parquet().unwrap()).unwrap();
+        for (i, (id, osm_id)) in check.id().iter().zip(check.osm_id()).enumerate() {
+            let first = check.osm_id().iter().position(|o| o == osm_id);
+            assert_eq!(deque.find_by_osm_id(osm_id), first);
+            assert_eq!(deque.find_index(&RoadKey(*id)), Some(i));
+        }
+
+        let deleted = deque.delete(&RoadKey(check.id()[10])).unwrap();
+        assert_eq!(deque.find_index(&RoadKey(deleted.id)), None);
+        assert_eq!(deque.find_index(&RoadKey(check.id()[11])), Some(10));
+        assert_eq!(deque.insert(deleted).0, check.id()[999] + 1);
+    }
+
+    #[test]
+    fn test_derived_tables() {
+        let mut classes = FeatureClass::default();
+        let row = |code: u16, fclass: &str| FeatureClassRow {
+            code,
+            fclass: fclass.into(),
+        };
+        classes.insert_many([row(5111, "motorway"), row(5141, "footway")]);
+        // deduplicated by key and by the unique feature class
+        assert_eq!(classes.insert(row(5111, "trunk")).0, 5111);
+        assert_eq!(classes.insert(row(1, "footway")).0, 5141);
+        assert_eq!(classes.len(), 2);
+        assert_eq!(classes.find_by_fclass("footway"), Some(1));
+
+        let mut names = Name::default();
+        let keys = names.insert_many(["a", "b", "a", "c"].map(|name| NameRow {
+            id: 42,
+            name: name.into(),
+        }));
+        assert_eq!(keys.iter().map(|k| k.0).collect::<Vec<_>>(), [0, 1, 0, 2]);
+        assert_eq!(names.delete(&NameKey(1)).unwrap().name, "b");
+        assert_eq!(names.find_by_name("c"), Some(1));
+        let rows: Vec<_> = names.rows().map(|row| (row.id, row.name)).collect();
+        assert_eq!(rows, [(0, "a".into()), (2, "c".into())]);
+
+        let mut refs = RefMany::default();
+        let key = refs.insert(RefManyRow {
+            road_id: 3,
+            ref_id: 4,
+        });
+        refs.insert(RefManyRow {
+            road_id: 3,
+            ref_id: 4,
+        });
+        assert_eq!(refs.len(), 1);
+        assert_eq!(refs.find_index(&RefManyKey(RoadKey(3), RefKey(4))), Some(0));
+        assert_eq!(refs.delete(&key).unwrap().ref_id, 4);
+        assert!(refs.is_empty());
+    }
+
+    #[test]
+    fn test_table_updates() {
+        let mut roads = Roads::default();
+        let keys = roads.insert_many((0..10).map(random_road));
+        let road = roads.get(&keys[3]).unwrap();
+        assert_eq!(roads.view(3).unwrap().osm_id, &road.osm_id);
+        assert_eq!(roads.iter().nth(3).unwrap().to_row().geom, road.geom);
+        assert_eq!(roads.iter().count(), 10);
+
+        let old = roads.set_maxspeed(&keys[3], 130).unwrap();
+        assert_eq!(old, road.maxspeed);
+        assert_eq!(*roads.view(3).unwrap().maxspeed, 130);
+        assert_eq!(
+            roads.set_maxspeed(&RoadKey(42), 30),
+            Err(UpdateError::NotFound)
+        );
+
+        // unique columns and keys stay unique, and are reindexed
+        let taken = roads.osm_id()[5];
+        assert_eq!(
+            roads.set_osm_id(&keys[3], taken),
+            Err(UpdateError::Duplicate("osm_id"))
+        );
+        roads.set_osm_id(&keys[3], road.osm_id + 1).unwrap();
+        assert_eq!(roads.find_by_osm_id(&(road.osm_id + 1)), Some(3));
+        assert_eq!(roads.find_by_osm_id(&road.osm_id), None);
+
+        let mut replacement = random_road(keys[3].0);
+        replacement.osm_id = 7;
+        let old = roads.update(&keys[3], replacement.clone()).unwrap();
+        assert_eq!(old.maxspeed, 130);
+        assert_eq!(roads.find_by_osm_id(&7), Some(3));
+        assert_eq!(roads.get(&keys[3]).unwrap().geom, replacement.geom);
+
+        let mut refs = RefMany::default();
+        let key = refs.insert(RefManyRow {
+            road_id: 3,
+            ref_id: 4,
+        });
+        refs.insert(RefManyRow {
+            road_id: 3,
+            ref_id: 5,
+        });
+        assert_eq!(refs.set_ref_id(&key, 5), Err(UpdateError::Duplicate("key")));
+        assert_eq!(refs.set_road_id(&key, 6), Ok(3));
+        assert_eq!(refs.find_index(&RefManyKey(RoadKey(6), RefKey(4))), Some(0));
+    }
+
+    #[test]
+    fn test_auto_increment_keys_stay_unique() {
+        let mut roads = Roads::default();
+        let keys = roads.insert_many((0..3).map(random_road));
+
+        // re-keying the first road to the next key would make the next insert assign it again
+        let mut rekeyed = roads.get(&keys[0]).unwrap();
+        rekeyed.id = 3;
+        assert_eq!(
+            roads.update(&keys[0], rekeyed).unwrap_err(),
+            UpdateError::KeyChanged
+        );
+
+        assert_eq!(roads.insert(random_road(0)).0, 3);
+        assert_eq!(roads.id(), [0, 1, 2, 3]);
+    }
 }